@@ -0,0 +1,52 @@
+//! This example demonstrates how to use the `AgentExecutor` to let the LLM use tools.
+//! Run like so: `cargo run --example agent`
+
+use orch::{execution::*, lm::Tool};
+
+mod example_utils;
+use example_utils::get_lm;
+
+async fn get_temperature(arguments: serde_json::Value) -> Result<String, String> {
+    let city = arguments["city"]
+        .as_str()
+        .ok_or("Missing 'city' argument")?;
+    match city.to_lowercase().as_str() {
+        "paris" => Ok("18°C".to_string()),
+        "london" => Ok("14°C".to_string()),
+        _ => Err(format!("No weather data for '{city}'")),
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let (lm, _) = get_lm();
+
+    let prompt = "Which is warmer right now, Paris or London?";
+
+    println!("Prompt: {prompt}");
+    println!("---");
+
+    let get_temperature_tool = Tool::new(
+        "get_temperature",
+        "Returns the current temperature in a city",
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "city": { "type": "string", "description": "Name of the city" }
+            },
+            "required": ["city"]
+        }),
+    );
+    let executor = AgentExecutorBuilder::new()
+        .with_lm(&*lm)
+        .with_tool(get_temperature_tool, get_temperature)
+        .with_max_steps(5)
+        .try_build()
+        .unwrap();
+    let response = executor.execute(prompt).await.expect("Execution failed");
+
+    println!("Response (after {} steps):", response.steps);
+    println!("{}", response.content);
+
+    assert!(response.content.contains("Paris"));
+}
@@ -0,0 +1,105 @@
+//! This example demonstrates how to use the `Executor` to stream a structured response from the LLM,
+//! rendering the fields as they arrive.
+//! Run like so: `cargo run --example structured_data_generation_stream`
+
+#![allow(dead_code)]
+
+use orch::execution::*;
+use orch::response::*;
+use tokio_stream::StreamExt;
+
+mod example_utils;
+use example_utils::get_lm;
+
+#[derive(Variants, serde::Deserialize)]
+pub enum ResponseVariants {
+    Answer(AnswerResponseVariant),
+    Fail(FailResponseVariant),
+}
+
+#[derive(Variant, serde::Deserialize)]
+#[variant(
+    variant = "Answer",
+    scenario = "You have reviewed the blog post",
+    description = "Suggestions for improving the blog post"
+)]
+pub struct AnswerResponseVariant {
+    #[schema(
+        description = "Suggestions for improving the blog post",
+        example = "[\"You wrote 'excellent' in two consecutive paragraphs in section 'Introduction'\"]"
+    )]
+    pub suggestions: Vec<String>,
+}
+
+#[derive(Variant, serde::Deserialize)]
+#[variant(
+    variant = "Fail",
+    scenario = "For some reason you failed to generate suggestions",
+    description = "Reason why you failed to generate suggestions"
+)]
+pub struct FailResponseVariant {
+    #[schema(
+        description = "Reason why you failed to generate suggestions",
+        example = "Content was invalid"
+    )]
+    pub reason: String,
+}
+
+#[tokio::main]
+async fn main() {
+    let (lm, _) = get_lm();
+
+    // Mock blog post
+    let prompt = "
+        This is a blog post about the importance of blogging.
+
+        # Introduction
+
+        Blogging is a crucial skill for any writer. It allows you to share your thoughts and ideas with others, and it can help you build a following and establish yourself as an expert in your field.
+    ";
+
+    let executor = StructuredExecutorBuilder::new()
+        .with_lm(&*lm)
+        .with_preamble("
+            You are an experienced writer and blog post reviewer who helps users improve their blog posts.
+            You will receive a blog post written in Markdown, and you will need to provide specific suggestions for improving it.
+        ")
+        .with_options(Box::new(variants!(ResponseVariants)))
+        .try_build()
+        .unwrap();
+    let mut response = executor
+        .execute_stream(prompt)
+        .await
+        .expect("Execution failed");
+
+    let mut rendered_suggestions = 0;
+    while let Some(event) = response.stream.next().await {
+        match event.expect("Execution failed") {
+            StructuredStreamEvent::Partial(partial) => {
+                // Render each suggestion once it is complete (i.e., once the next one has started).
+                let suggestions = partial["suggestions"]
+                    .as_array()
+                    .cloned()
+                    .unwrap_or_default();
+                for suggestion in suggestions
+                    .iter()
+                    .take(suggestions.len().saturating_sub(1))
+                    .skip(rendered_suggestions)
+                {
+                    println!("- {}", suggestion.as_str().unwrap_or_default());
+                    rendered_suggestions += 1;
+                }
+            }
+            StructuredStreamEvent::Done(ResponseVariants::Answer(answer)) => {
+                for suggestion in answer.suggestions.iter().skip(rendered_suggestions) {
+                    println!("- {}", suggestion);
+                }
+                assert!(!answer.suggestions.is_empty());
+            }
+            StructuredStreamEvent::Done(ResponseVariants::Fail(fail)) => {
+                eprintln!("Model failed to generate a response: {}", fail.reason);
+                std::process::exit(1);
+            }
+        }
+    }
+}
@@ -1,7 +1,7 @@
 //! This example demonstrates how to use the `Executor` to generate a streaming response from the LLM.
 //! Run like so: `cargo run --example text_generation_stream`
 
-use orch::{execution::*, lm::LanguageModelProvider};
+use orch::execution::*;
 use tokio_stream::StreamExt;
 
 mod example_utils;
@@ -9,12 +9,7 @@ use example_utils::get_lm;
 
 #[tokio::main]
 async fn main() {
-    let (lm, provider) = get_lm();
-
-    if provider == LanguageModelProvider::Anthropic {
-        println!("Streaming is not currently supported for Anthropic. Skipping example.");
-        return;
-    }
+    let (lm, _) = get_lm();
 
     let prompt = "What is 2+2?";
 
@@ -46,5 +41,13 @@ async fn main() {
     }
     println!();
 
+    if let Some(usage) = response.metadata.usage() {
+        println!("---");
+        println!(
+            "Prompt tokens: {}, completion tokens: {}",
+            usage.prompt_tokens, usage.completion_tokens
+        );
+    }
+
     assert!(!response_text.is_empty());
 }
@@ -0,0 +1,208 @@
+use async_trait::async_trait;
+use orch_response::{validate_json_schema, OrchResponseVariants};
+
+use super::{AlignmentError, AlignmentIteration, AlignmentVerdict};
+
+/// A request to a judge to review a candidate response.
+#[derive(Debug, Clone, Copy)]
+pub struct JudgeRequest<'r> {
+    /// The instructions (preamble) which the base model received.
+    pub preamble: &'r str,
+    /// The prompt which the base model received.
+    pub prompt: &'r str,
+    /// The candidate response to review.
+    pub candidate: &'r str,
+    /// The last iteration in which a correction was requested, if any.
+    pub prev_correction: Option<&'r AlignmentIteration>,
+}
+
+/// The verdict of a judge, along with its reason.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlignmentJudgement {
+    pub verdict: AlignmentVerdict,
+    pub reason: String,
+}
+
+impl AlignmentJudgement {
+    pub fn new(verdict: AlignmentVerdict, reason: impl Into<String>) -> Self {
+        Self {
+            verdict,
+            reason: reason.into(),
+        }
+    }
+}
+
+/// A judge which reviews the responses of a base model during alignment (see [`super::AlignmentStrategy`]).
+///
+/// Judges are either deterministic validators (e.g., [`JsonSchemaJudge`] or [`FnJudge`])
+/// or another language model ([`super::LanguageModelJudge`]).
+/// A correction (or failure message) of a judge is fed back to the base model when it re-generates its response.
+#[async_trait]
+pub trait AlignmentJudge: Send + Sync {
+    /// Name of the judge, as recorded in the [`super::AlignmentReport`].
+    fn name(&self) -> String;
+
+    /// Reviews a candidate response.
+    async fn judge(&self, request: &JudgeRequest<'_>)
+        -> Result<AlignmentJudgement, AlignmentError>;
+}
+
+/// A judge which requires the response to be JSON which matches a JSON Schema.
+#[derive(Debug, Clone)]
+pub struct JsonSchemaJudge {
+    schema: serde_json::Value,
+}
+
+impl JsonSchemaJudge {
+    pub fn new(schema: serde_json::Value) -> Self {
+        Self { schema }
+    }
+
+    /// Creates a judge with the JSON Schema of the response variants.
+    pub fn from_variants<T>(variants: &dyn OrchResponseVariants<T>) -> Self {
+        Self::new(variants.json_schema())
+    }
+}
+
+#[async_trait]
+impl AlignmentJudge for JsonSchemaJudge {
+    fn name(&self) -> String {
+        "json_schema".to_string()
+    }
+
+    async fn judge(
+        &self,
+        request: &JudgeRequest<'_>,
+    ) -> Result<AlignmentJudgement, AlignmentError> {
+        let value = match serde_json::from_str(request.candidate) {
+            Ok(value) => value,
+            Err(e) => {
+                let reason = format!("The response is not valid JSON: {e}");
+                return Ok(AlignmentJudgement::new(
+                    AlignmentVerdict::SchemaCorrection(reason.clone()),
+                    reason,
+                ));
+            }
+        };
+        match validate_json_schema(&self.schema, &value) {
+            Ok(()) => Ok(AlignmentJudgement::new(
+                AlignmentVerdict::NoCorrection,
+                "The response matches the JSON Schema",
+            )),
+            Err(errors) => {
+                let errors = errors
+                    .iter()
+                    .map(|error| format!("\n- {error}"))
+                    .collect::<String>();
+                Ok(AlignmentJudgement::new(
+                    AlignmentVerdict::SchemaCorrection(format!(
+                        "The response does not match the JSON Schema:{errors}"
+                    )),
+                    "The response does not match the JSON Schema",
+                ))
+            }
+        }
+    }
+}
+
+/// A judge which validates the (raw) response with a function,
+/// which returns an error message if the response is invalid (e.g., a regex which does not match).
+pub struct FnJudge<F> {
+    name: String,
+    validate: F,
+}
+
+impl<F> FnJudge<F>
+where
+    F: Fn(&str) -> Result<(), String> + Send + Sync,
+{
+    pub fn new(name: impl Into<String>, validate: F) -> Self {
+        Self {
+            name: name.into(),
+            validate,
+        }
+    }
+}
+
+#[async_trait]
+impl<F> AlignmentJudge for FnJudge<F>
+where
+    F: Fn(&str) -> Result<(), String> + Send + Sync,
+{
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    async fn judge(
+        &self,
+        request: &JudgeRequest<'_>,
+    ) -> Result<AlignmentJudgement, AlignmentError> {
+        Ok(match (self.validate)(request.candidate) {
+            Ok(()) => {
+                AlignmentJudgement::new(AlignmentVerdict::NoCorrection, "The response is valid")
+            }
+            Err(message) => AlignmentJudgement::new(
+                AlignmentVerdict::ResponseCorrection(message.clone()),
+                message,
+            ),
+        })
+    }
+}
+
+/// A judge which parses the response into one of the response variants,
+/// and validates the parsed response with a function (e.g., "the shell command must parse").
+pub struct ParsedResponseJudge<T, F> {
+    name: String,
+    variants: Box<dyn OrchResponseVariants<T>>,
+    validate: F,
+}
+
+impl<T, F> ParsedResponseJudge<T, F>
+where
+    F: Fn(&T) -> Result<(), String> + Send + Sync,
+{
+    pub fn new(
+        name: impl Into<String>,
+        variants: Box<dyn OrchResponseVariants<T>>,
+        validate: F,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            variants,
+            validate,
+        }
+    }
+}
+
+#[async_trait]
+impl<T, F> AlignmentJudge for ParsedResponseJudge<T, F>
+where
+    T: Send,
+    F: Fn(&T) -> Result<(), String> + Send + Sync,
+{
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    async fn judge(
+        &self,
+        request: &JudgeRequest<'_>,
+    ) -> Result<AlignmentJudgement, AlignmentError> {
+        let judgement = match self.variants.parse(request.candidate) {
+            Ok(response) => match (self.validate)(&response) {
+                Ok(()) => {
+                    AlignmentJudgement::new(AlignmentVerdict::NoCorrection, "The response is valid")
+                }
+                Err(message) => AlignmentJudgement::new(
+                    AlignmentVerdict::ResponseCorrection(message.clone()),
+                    message,
+                ),
+            },
+            Err(e) => {
+                let reason = format!("The response could not be parsed: {e}");
+                AlignmentJudgement::new(AlignmentVerdict::SchemaCorrection(reason.clone()), reason)
+            }
+        };
+        Ok(judgement)
+    }
+}
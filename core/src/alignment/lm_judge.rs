@@ -0,0 +1,202 @@
+use async_trait::async_trait;
+use orch_response_derive::{variants, Variant, Variants};
+
+use super::{AlignmentError, AlignmentJudge, AlignmentJudgement, AlignmentVerdict, JudgeRequest};
+use crate::{
+    execution::{ExecutorError, StructuredExecutor, StructuredExecutorBuilder},
+    lm::LanguageModel,
+};
+
+/// A judge which asks a language model to review the response, and to correct it if needed.
+#[derive(Clone)]
+pub struct LanguageModelJudge<'a> {
+    lm: &'a dyn LanguageModel,
+}
+
+#[derive(Variants, Clone, serde::Deserialize)]
+pub enum AlignmentResponse {
+    ResponseCorrection(ResponseCorrectionResponseVariant),
+    SchemaCorrection(SchemaCorrectionResponseVariant),
+    NoCorrection(NoCorrectionResponseVariant),
+    Fail(FailResponseVariant),
+}
+
+#[derive(Variant, Clone, serde::Deserialize)]
+#[variant(
+    variant = "ResponseCorrection",
+    scenario = "The response format is correct, but the response content itself is incorrect",
+    description = "A correction and a reason why it is needed"
+)]
+pub struct ResponseCorrectionResponseVariant {
+    #[schema(
+        description = "Correction of the phrase",
+        example = "{ \"capital\": \"Paris\" }"
+    )]
+    pub correction: String,
+
+    #[schema(
+        description = "Short reason why a correction is needed",
+        example = "The capital of France is not London as the original model returned, but Paris"
+    )]
+    pub reason: String,
+}
+
+#[derive(Variant, Clone, serde::Deserialize)]
+#[variant(
+    variant = "SchemaCorrection",
+    scenario = "The schema of the response is incorrect",
+    description = "Explanation of why the schema is incorrect"
+)]
+pub struct SchemaCorrectionResponseVariant {
+    #[schema(
+        description = "Correction of the schema, in natural language",
+        example = "\"'capital' should be a string, not a number'\" or \"The 'capital' field has a typo and starts with an uppercase letter\""
+    )]
+    pub correction: String,
+
+    #[schema(
+        description = "Short reason why a correction is needed",
+        example = "The 'capital' field is a number, not a string"
+    )]
+    pub reason: String,
+}
+
+#[derive(Variant, Clone, serde::Deserialize)]
+#[variant(
+    variant = "NoCorrection",
+    scenario = "No correction needed, the original response satisfies the expected output",
+    description = "Short reason why a correction is not needed"
+)]
+pub struct NoCorrectionResponseVariant {
+    #[schema(
+        description = "Short reason why a correction is not needed",
+        example = "The user asked for the capital city of France, and the answer is indeed Paris"
+    )]
+    pub reason: String,
+}
+
+#[derive(Variant, Clone, serde::Deserialize)]
+#[variant(
+    variant = "Fail",
+    scenario = "You don't know how to verify whether the answer is correct or not. You should only go for this response in extreme cases",
+    description = "Reason why you failed to determine whether the answer is correct or not"
+)]
+pub struct FailResponseVariant {
+    #[schema(
+        description = "Reason why you failed to determine whether the answer is correct or not",
+        example = "The question is extremely vague and the model returned something completely unrelated"
+    )]
+    pub reason: String,
+}
+
+impl<'a> LanguageModelJudge<'a> {
+    const PREAMBLE: &'static str = "
+    Your purpose is to receive a response from a language model and make sure (and correct otherwise) whether the response is expected or not.  
+    Being \"expected\" means that the response is correct and matches the expected output.
+
+    You should *not* return the response in the schema of the original message, but instead of the schema that you are requested to provide
+    (the one with the response types 'ResponseCorrection', 'SchemaCorrection' and 'NoCorrection').
+    ";
+
+    pub fn new(lm: &'a dyn LanguageModel) -> Self {
+        Self { lm }
+    }
+}
+
+#[async_trait]
+impl AlignmentJudge for LanguageModelJudge<'_> {
+    fn name(&self) -> String {
+        format!("lm:{}", self.lm.text_completion_model_name())
+    }
+
+    async fn judge(
+        &self,
+        request: &JudgeRequest<'_>,
+    ) -> Result<AlignmentJudgement, AlignmentError> {
+        let mut preamble = format!(
+            "
+            {base_preamble}
+
+            The model received the original instructions:
+            {original_preamble}
+
+            And the original prompt:
+            {original_prompt}
+
+            And the original response:
+            {candidate}
+
+            REMEMBER: Return a response in the schema you are requested (the one with the response types 'ResponseCorrection', 'SchemaCorrection' and 'NoCorrection').
+    ",
+            base_preamble = Self::PREAMBLE,
+            original_preamble = request.preamble,
+            original_prompt = request.prompt,
+            candidate = request.candidate,
+        );
+        // If there is no previous correction, then this was the first attempt and no additional preamble is needed.
+        if let Some(prev_correction) = request.prev_correction {
+            // TODO: Add context of more tries?
+            preamble.push_str(&format!(
+                "
+                IMPORTANT CONTEXT:
+                Before receiving the previous correction, the model has already responded with the following:
+
+                {}
+
+                And received the following corrections:
+                ",
+                prev_correction.candidate
+            ));
+
+            match &prev_correction.verdict {
+                AlignmentVerdict::ResponseCorrection(correction) => {
+                    preamble.push_str(&format!(
+                        "CORRECTION: The response content was incorrect, this is the correction: {}",
+                        correction,
+                    ));
+                }
+                AlignmentVerdict::SchemaCorrection(correction) => {
+                    preamble.push_str(&format!(
+                        "CORRECTION: The response schema was incorrect for the following reason: {}
+                        This is the correction: {}
+                        ",
+                        prev_correction.reason, correction
+                    ));
+                }
+                _ => {
+                    // No error (this is unexpected) - return the original response.
+                    return Err(AlignmentError::InternalError(
+                        "Requested correction with no relevant correction response".to_owned(),
+                    ));
+                }
+            }
+        }
+
+        let executor: StructuredExecutor<AlignmentResponse> = StructuredExecutorBuilder::new()
+            .with_lm(self.lm)
+            .with_preamble(&preamble)
+            .with_options(Box::new(variants!(AlignmentResponse)))
+            .try_build()
+            .unwrap();
+        let (verdict, reason) = match executor.execute(request.prompt).await {
+            Ok(response) => match response.content {
+                AlignmentResponse::NoCorrection(response) => {
+                    (AlignmentVerdict::NoCorrection, response.reason)
+                }
+                AlignmentResponse::ResponseCorrection(response) => (
+                    AlignmentVerdict::ResponseCorrection(response.correction),
+                    response.reason,
+                ),
+                AlignmentResponse::SchemaCorrection(response) => (
+                    AlignmentVerdict::SchemaCorrection(response.correction),
+                    response.reason,
+                ),
+                AlignmentResponse::Fail(response) => (AlignmentVerdict::Fail, response.reason),
+            },
+            Err(ExecutorError::Parsing(e)) => (AlignmentVerdict::Unparseable, e),
+            Err(e) => return Err(AlignmentError::ExecutionFailed(e.to_string())),
+        };
+
+        Ok(AlignmentJudgement { verdict, reason })
+    }
+}
@@ -5,8 +5,14 @@
 //! This concept has similarities to to traditional "resilience" techniques and libraries, such as .NET's [Polly](https://github.com/App-vNext/Polly),
 //! which I personally like a lot.
 
+mod judge;
+mod lm_judge;
+mod report;
 mod strategy;
 mod strategy_builder;
 
+pub use judge::*;
+pub use lm_judge::*;
+pub use report::*;
 pub use strategy::*;
 pub use strategy_builder::*;
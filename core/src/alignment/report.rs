@@ -0,0 +1,75 @@
+/// The verdict of the judge on a candidate response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AlignmentVerdict {
+    /// The candidate is correct, so it is used as is.
+    NoCorrection,
+    /// The content of the candidate is incorrect, along with the correction.
+    ResponseCorrection(String),
+    /// The schema of the candidate is incorrect, along with the correction (in natural language).
+    SchemaCorrection(String),
+    /// The judge could not determine whether the candidate is correct.
+    Fail,
+    /// The response of the judge could not be parsed.
+    Unparseable,
+}
+
+impl AlignmentVerdict {
+    /// Returns the correction suggested by the judge, if any.
+    pub fn correction(&self) -> Option<&str> {
+        match self {
+            AlignmentVerdict::ResponseCorrection(correction)
+            | AlignmentVerdict::SchemaCorrection(correction) => Some(correction),
+            _ => None,
+        }
+    }
+}
+
+/// A single iteration of the alignment, in which the judge reviewed a candidate response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlignmentIteration {
+    /// Name of the judge which reviewed the candidate (see [`super::AlignmentJudge::name`]).
+    pub judge: String,
+    /// The candidate response which was reviewed.
+    pub candidate: String,
+    /// The verdict of the judge.
+    pub verdict: AlignmentVerdict,
+    /// The reason the judge gave for its verdict (or the parsing error, if the verdict is unparseable).
+    pub reason: String,
+}
+
+/// The final outcome of the alignment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AlignmentOutcome {
+    /// The judge accepted the response.
+    Accepted {
+        /// The accepted response.
+        response: String,
+    },
+    /// The judge did not accept a response within the allowed number of re-generations of the base model.
+    MaxRetriesExceeded,
+    /// The judge used up its calls, and its last responses could not be parsed.
+    JudgeUnparseable,
+    /// The judge used up its calls without accepting a response.
+    MaxJudgeCallsExceeded,
+    /// The alignment did not finish within the timeout.
+    TimedOut,
+}
+
+/// A report of the alignment of a response, which explains why (and how) a response was changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlignmentReport {
+    /// The iterations of the alignment, in order.
+    pub iterations: Vec<AlignmentIteration>,
+    /// The final outcome of the alignment.
+    pub outcome: AlignmentOutcome,
+}
+
+impl AlignmentReport {
+    /// Returns the accepted response, if the alignment succeeded.
+    pub fn response(&self) -> Option<&str> {
+        match &self.outcome {
+            AlignmentOutcome::Accepted { response } => Some(response),
+            _ => None,
+        }
+    }
+}
@@ -1,10 +1,14 @@
-use async_recursion::async_recursion;
-use orch_response_derive::{variants, Variant, Variants};
+use std::time::Duration;
+
 use thiserror::Error;
 
+use super::{
+    AlignmentIteration, AlignmentJudge, AlignmentOutcome, AlignmentReport, AlignmentVerdict,
+    JudgeRequest,
+};
 use crate::{
-    execution::{ExecutorError, StructuredExecutor, StructuredExecutorBuilder},
-    lm::{LanguageModel, LanguageModelError, TextCompleteOptions},
+    execution::{text_complete, ExecutorContext},
+    lm::{GenerationOptions, LanguageModel, LanguageModelError, ResponseFormat},
 };
 
 #[derive(Debug, Error)]
@@ -18,263 +22,455 @@ pub enum AlignmentError {
     #[error("Internal error: {0}")]
     InternalError(String),
 
-    #[error("Max retries exceeded ({0} retries)")]
-    MaxRetriesExceeded(usize),
-}
-
-pub struct AlignmentStrategy<'a> {
-    pub(crate) lm: &'a dyn LanguageModel,
-    pub(crate) retries: usize,
+    #[error("Max retries exceeded ({retries} retries)")]
+    MaxRetriesExceeded {
+        retries: usize,
+        /// Report of the alignment, including the verdicts of the judge for each attempt.
+        report: Box<AlignmentReport>,
+    },
+
+    #[error("The responses of the judge could not be parsed ({attempts} attempts)")]
+    JudgeUnparseable {
+        attempts: usize,
+        /// Report of the alignment, including the parsing errors.
+        report: Box<AlignmentReport>,
+    },
+
+    #[error("Max judge calls exceeded ({calls} calls)")]
+    MaxJudgeCallsExceeded {
+        calls: usize,
+        /// Report of the alignment, including the verdicts of the judge for each attempt.
+        report: Box<AlignmentReport>,
+    },
+
+    #[error("Alignment timed out after {timeout:?}")]
+    Timeout {
+        timeout: Duration,
+        /// Report of the alignment up to the timeout.
+        report: Box<AlignmentReport>,
+    },
 }
 
-#[derive(Variants, Clone, serde::Deserialize)]
-pub enum AlignmentResponse {
-    ResponseCorrection(ResponseCorrectionResponseVariant),
-    SchemaCorrection(SchemaCorrectionResponseVariant),
-    NoCorrection(NoCorrectionResponseVariant),
-    Fail(FailResponseVariant),
+/// Settings of the original generation of the base model, which are kept for its re-generations.
+#[derive(Debug, Clone, Default)]
+pub struct BaseGeneration {
+    /// Sampling parameters (e.g., temperature) of the base model.
+    pub generation: GenerationOptions,
+    /// Format of the response to request from the provider.
+    pub response_format: ResponseFormat,
+    /// Context of the conversation preceding the original prompt.
+    pub context: ExecutorContext,
 }
 
-#[derive(Variant, Clone, serde::Deserialize)]
-#[variant(
-    variant = "ResponseCorrection",
-    scenario = "The response format is correct, but the response content itself is incorrect",
-    description = "A correction and a reason why it is needed"
-)]
-pub struct ResponseCorrectionResponseVariant {
-    #[schema(
-        description = "Correction of the phrase",
-        example = "{ \"capital\": \"Paris\" }"
-    )]
-    pub correction: String,
-
-    #[schema(
-        description = "Short reason why a correction is needed",
-        example = "The capital of France is not London as the original model returned, but Paris"
-    )]
-    pub reason: String,
-}
-
-#[derive(Variant, Clone, serde::Deserialize)]
-#[variant(
-    variant = "SchemaCorrection",
-    scenario = "The schema of the response is incorrect",
-    description = "Explanation of why the schema is incorrect"
-)]
-pub struct SchemaCorrectionResponseVariant {
-    #[schema(
-        description = "Correction of the schema, in natural language",
-        example = "\"'capital' should be a string, not a number'\" or \"The 'capital' field has a typo and starts with an uppercase letter\""
-    )]
-    pub correction: String,
-
-    #[schema(
-        description = "Short reason why a correction is needed",
-        example = "The 'capital' field is a number, not a string"
-    )]
-    pub reason: String,
-}
-
-#[derive(Variant, Clone, serde::Deserialize)]
-#[variant(
-    variant = "NoCorrection",
-    scenario = "No correction needed, the original response satisfies the expected output",
-    description = "Short reason why a correction is not needed"
-)]
-pub struct NoCorrectionResponseVariant {
-    #[schema(
-        description = "Short reason why a correction is not needed",
-        example = "The user asked for the capital city of France, and the answer is indeed Paris"
-    )]
-    pub reason: String,
-}
-
-#[derive(Variant, Clone, serde::Deserialize)]
-#[variant(
-    variant = "Fail",
-    scenario = "You don't know how to verify whether the answer is correct or not. You should only go for this response in extreme cases",
-    description = "Reason why you failed to determine whether the answer is correct or not"
-)]
-pub struct FailResponseVariant {
-    #[schema(
-        description = "Reason why you failed to determine whether the answer is correct or not",
-        example = "The question is extremely vague and the model returned something completely unrelated"
-    )]
-    pub reason: String,
+pub struct AlignmentStrategy<'a> {
+    /// The judges which review each candidate response, in order.
+    pub(crate) judges: Vec<Box<dyn AlignmentJudge + 'a>>,
+    /// Maximum number of re-generations of the base model.
+    pub(crate) retries: usize,
+    /// Maximum number of judge reviews.
+    pub(crate) max_judge_calls: usize,
+    /// Maximum wall-clock time of the alignment.
+    pub(crate) timeout: Option<Duration>,
 }
 
 impl<'a> AlignmentStrategy<'a> {
-    const PREAMBLE: &'static str = "
-    Your purpose is to receive a response from a language model and make sure (and correct otherwise) whether the response is expected or not.  
-    Being \"expected\" means that the response is correct and matches the expected output.
-
-    You should *not* return the response in the schema of the original message, but instead of the schema that you are requested to provide
-    (the one with the response types 'ResponseCorrection', 'SchemaCorrection' and 'NoCorrection').
-    ";
-
     /// Aligns the response of the language model.
     /// Tries at least once, and continues according to the [`AlignmentStrategy`]
-    /// (e.g., number of retries).
+    /// (i.e., the budgets of judge calls and re-generations, and the timeout).
+    ///
+    /// Re-generations of the base model use the same settings as the original generation (see [`BaseGeneration`]).
+    ///
+    /// Returns a report with the verdict of the judge for each candidate response, and the accepted response.
     pub async fn align(
         &self,
         base_lm: &'a dyn LanguageModel,
         original_preamble: &str,
         original_prompt: &str,
         original_response: &str,
-    ) -> Result<String, AlignmentError> {
-        let mut iterated_response = original_response.to_owned();
-        let mut retry_count = 0;
-        let mut prev_alignment_response = None;
+        base_generation: &BaseGeneration,
+    ) -> Result<AlignmentReport, AlignmentError> {
+        let mut iterations = Vec::new();
+        let iterate = self.align_iterations(
+            base_lm,
+            original_preamble,
+            original_prompt,
+            original_response,
+            base_generation,
+            &mut iterations,
+        );
+        let outcome = match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, iterate)
+                .await
+                .unwrap_or(Ok(AlignmentOutcome::TimedOut)),
+            None => iterate.await,
+        }?;
+
+        let report = AlignmentReport {
+            iterations,
+            outcome,
+        };
+        match report.outcome {
+            AlignmentOutcome::Accepted { .. } => Ok(report),
+            AlignmentOutcome::MaxRetriesExceeded => Err(AlignmentError::MaxRetriesExceeded {
+                retries: self.retries,
+                report: Box::new(report),
+            }),
+            AlignmentOutcome::JudgeUnparseable => Err(AlignmentError::JudgeUnparseable {
+                attempts: report
+                    .iterations
+                    .iter()
+                    .rev()
+                    .take_while(|iteration| iteration.verdict == AlignmentVerdict::Unparseable)
+                    .count(),
+                report: Box::new(report),
+            }),
+            AlignmentOutcome::MaxJudgeCallsExceeded => Err(AlignmentError::MaxJudgeCallsExceeded {
+                calls: self.max_judge_calls,
+                report: Box::new(report),
+            }),
+            AlignmentOutcome::TimedOut => Err(AlignmentError::Timeout {
+                timeout: self.timeout.unwrap_or_default(),
+                report: Box::new(report),
+            }),
+        }
+    }
 
-        loop {
-            let response = self
-                .request_correction(
-                    original_preamble,
-                    original_prompt,
-                    &iterated_response,
-                    &prev_alignment_response,
-                )
-                .await?;
+    /// Runs the alignment loop, recording each iteration in `iterations` (so that they are kept on a timeout).
+    async fn align_iterations(
+        &self,
+        base_lm: &'a dyn LanguageModel,
+        original_preamble: &str,
+        original_prompt: &str,
+        original_response: &str,
+        base_generation: &BaseGeneration,
+        iterations: &mut Vec<AlignmentIteration>,
+    ) -> Result<AlignmentOutcome, AlignmentError> {
+        let mut candidate = original_response.to_owned();
+        let mut regenerations = 0;
 
-            let Some(response) = response else {
-                // The response may be `None` if the correction deemed that the previous response should be used.
-                continue;
-            };
+        loop {
+            if iterations.len() >= self.max_judge_calls {
+                let unparseable = iterations
+                    .last()
+                    .is_some_and(|iteration| iteration.verdict == AlignmentVerdict::Unparseable);
+                return Ok(if unparseable {
+                    AlignmentOutcome::JudgeUnparseable
+                } else {
+                    AlignmentOutcome::MaxJudgeCallsExceeded
+                });
+            }
 
-            match &response {
-                AlignmentResponse::NoCorrection(_) => {
-                    // Found no correction, can return the original response.
-                    return Ok(iterated_response.to_owned());
+            let prev_correction = iterations
+                .iter()
+                .rev()
+                .find(|iteration| iteration.verdict.correction().is_some());
+            let iteration = self
+                .review(&JudgeRequest {
+                    preamble: original_preamble,
+                    prompt: original_prompt,
+                    candidate: &candidate,
+                    prev_correction,
+                })
+                .await?;
+            let verdict = iteration.verdict.clone();
+            iterations.push(iteration);
+
+            let correction = match verdict {
+                AlignmentVerdict::NoCorrection => {
+                    // Found no correction, can return the candidate response.
+                    return Ok(AlignmentOutcome::Accepted {
+                        response: candidate,
+                    });
                 }
-                response => {
-                    retry_count += 1;
-
-                    if retry_count >= self.retries {
-                        return Err(AlignmentError::MaxRetriesExceeded(retry_count));
-                    }
-
-                    if let AlignmentResponse::Fail(_) = response {
-                        // Failed - simply try again.
-                        continue;
-                    }
-
-                    let correction = match response {
-                        AlignmentResponse::ResponseCorrection(response_correction) => {
-                            response_correction.correction.clone()
-                        }
-                        AlignmentResponse::SchemaCorrection(schema_correction) => {
-                            schema_correction.correction.clone()
-                        }
-                        _ => unreachable!(),
-                    };
-
-                    let correction_prompt = format!("
-                        {original_preamble}
-
-                        NOTE:
-                        You have previously answered this with the following response and was incorrect. Here is the response and the correction, please make sure not to repeat the same mistake:
-                        ORIGINAL RESPONSE: {original_response}
-                        CORRECTION: {correction}
-                        ");
-                    let new_base_model_response = base_lm
-                        .text_complete(
-                            original_prompt,
-                            &correction_prompt,
-                            TextCompleteOptions::default(),
-                        )
-                        .await
-                        .map_err(AlignmentError::LanguageModelError)?;
-
-                    prev_alignment_response = Some(response.clone());
-                    iterated_response = new_base_model_response.text;
+                AlignmentVerdict::Unparseable | AlignmentVerdict::Fail => {
+                    // The judge should review the same response again (within its budget of calls).
+                    continue;
                 }
+                AlignmentVerdict::ResponseCorrection(correction)
+                | AlignmentVerdict::SchemaCorrection(correction) => correction,
+            };
+
+            if regenerations >= self.retries {
+                return Ok(AlignmentOutcome::MaxRetriesExceeded);
             }
+            regenerations += 1;
+
+            let correction_prompt = format!("
+                {original_preamble}
+
+                NOTE:
+                You have previously answered this with the following response and was incorrect. Here is the response and the correction, please make sure not to repeat the same mistake:
+                PREVIOUS RESPONSE: {candidate}
+                CORRECTION: {correction}
+                ");
+            let new_base_model_response = text_complete(
+                base_lm,
+                original_prompt,
+                &correction_prompt,
+                &base_generation.generation,
+                &base_generation.response_format,
+                &base_generation.context,
+            )
+            .await
+            .map_err(|e| AlignmentError::ExecutionFailed(e.to_string()))?;
+
+            candidate = new_base_model_response.content;
         }
     }
 
-    #[async_recursion]
-    async fn request_correction(
+    /// Runs the judges in order, stopping at the first judge which does not accept the candidate.
+    async fn review(
         &self,
-        original_preamble: &str,
-        original_prompt: &str,
-        original_response: &str,
-        prev_alignment_response: &Option<AlignmentResponse>,
-    ) -> Result<Option<AlignmentResponse>, AlignmentError> {
-        let mut preamble = format!(
-            "
-            {base_preamble}
+        request: &JudgeRequest<'_>,
+    ) -> Result<AlignmentIteration, AlignmentError> {
+        let mut iteration = None;
+        for judge in &self.judges {
+            let judgement = judge.judge(request).await?;
+            let accepted = judgement.verdict == AlignmentVerdict::NoCorrection;
+            iteration = Some(AlignmentIteration {
+                judge: judge.name(),
+                candidate: request.candidate.to_owned(),
+                verdict: judgement.verdict,
+                reason: judgement.reason,
+            });
+            if !accepted {
+                break;
+            }
+        }
+        iteration
+            .ok_or_else(|| AlignmentError::InternalError("No judges are configured".to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        alignment::{AlignmentStrategyBuilder, FnJudge, JsonSchemaJudge},
+        test_utils::ScriptedLanguageModel,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_align_report() {
+        let base_lm = ScriptedLanguageModel::new(&[r#"{"capital": "Paris"}"#]);
+        let judge_lm = ScriptedLanguageModel::new(&[
+            r#"{"response_type": "ResponseCorrection", "correction": "{\"capital\": \"Paris\"}", "reason": "The capital of France is Paris"}"#,
+            r#"{"response_type": "NoCorrection", "reason": "Paris is correct"}"#,
+        ]);
+        let strategy = AlignmentStrategyBuilder::new()
+            .with_lm(&judge_lm)
+            .with_retries(3)
+            .try_build()
+            .unwrap();
 
-            The model received the original instructions:
-            {original_preamble}
+        let report = strategy
+            .align(
+                &base_lm,
+                "",
+                "Capital of France?",
+                r#"{"capital": "London"}"#,
+                &BaseGeneration::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(report.response(), Some(r#"{"capital": "Paris"}"#));
+        assert_eq!(report.iterations.len(), 2);
+        assert_eq!(report.iterations[0].candidate, r#"{"capital": "London"}"#);
+        assert_eq!(
+            report.iterations[0].verdict,
+            AlignmentVerdict::ResponseCorrection(r#"{"capital": "Paris"}"#.to_string())
+        );
+        assert_eq!(
+            report.iterations[0].reason,
+            "The capital of France is Paris"
+        );
+        assert_eq!(report.iterations[1].verdict, AlignmentVerdict::NoCorrection);
+    }
 
-            And the original prompt:
-            {original_prompt}
+    #[tokio::test]
+    async fn test_align_max_retries() {
+        let correction = r#"{"response_type": "ResponseCorrection", "correction": "Paris", "reason": "Wrong capital"}"#;
+        let base_lm = ScriptedLanguageModel::new(&["Lyon"]);
+        let judge_lm = ScriptedLanguageModel::new(&[correction, correction]);
+        let strategy = AlignmentStrategyBuilder::new()
+            .with_lm(&judge_lm)
+            .with_retries(1)
+            .try_build()
+            .unwrap();
 
-            And the original response:
-            {original_response}
+        let result = strategy
+            .align(
+                &base_lm,
+                "",
+                "Capital?",
+                "London",
+                &BaseGeneration::default(),
+            )
+            .await;
+        let Err(AlignmentError::MaxRetriesExceeded { retries, report }) = result else {
+            panic!("Expected the retries to be exceeded");
+        };
+        assert_eq!(retries, 1);
+        assert_eq!(report.outcome, AlignmentOutcome::MaxRetriesExceeded);
+        assert_eq!(report.iterations[1].candidate, "Lyon");
+        assert_eq!(report.iterations[1].reason, "Wrong capital");
+    }
 
-            REMEMBER: Return a response in the schema you are requested (the one with the response types 'ResponseCorrection', 'SchemaCorrection' and 'NoCorrection').
-    ",
-            base_preamble = Self::PREAMBLE,
-        );
-        // If `alignment_response` is `None`, then this was the first attempt and no additional preamble is needed.
-        if let Some(prev_alignment_response) = prev_alignment_response {
-            // TODO: Add context of more tries?
-            preamble.push_str(&format!(
-                "
-                IMPORTANT CONTEXT:
-                Before receiving the previous correction, the model has already responded with the following:
-
-                {}
-
-                And received the following corrections:
-                ",
-                original_response
-            ));
-
-            match prev_alignment_response {
-                AlignmentResponse::ResponseCorrection(response_correction) => {
-                    preamble.push_str(&format!(
-                        "CORRECTION: The response content was incorrect, this is the correction: {}",
-                        response_correction.correction,
-                    ));
-                }
-                AlignmentResponse::SchemaCorrection(schema_correction) => {
-                    preamble.push_str(&format!(
-                        "CORRECTION: The response schema was incorrect for the following reason: {}
-                        This is the correction: {}
-                        ",
-                        schema_correction.correction, schema_correction.reason
-                    ));
-                }
-                _ => {
-                    // No error (this is unexpected) - return the original response.
-                    return Err(AlignmentError::InternalError(
-                        "Requested correction with no relevant correction response".to_owned(),
-                    ));
-                }
-            }
-        }
+    #[tokio::test]
+    async fn test_align_correction_prompt() {
+        let base_lm = ScriptedLanguageModel::new(&["Lyon", "Paris"]);
+        let judge_lm = ScriptedLanguageModel::new(&[
+            r#"{"response_type": "ResponseCorrection", "correction": "Not London", "reason": "Wrong capital"}"#,
+            r#"{"response_type": "ResponseCorrection", "correction": "Not Lyon", "reason": "Wrong capital"}"#,
+            r#"{"response_type": "NoCorrection", "reason": "Paris is correct"}"#,
+        ]);
+        let strategy = AlignmentStrategyBuilder::new()
+            .with_lm(&judge_lm)
+            .with_retries(2)
+            .try_build()
+            .unwrap();
+
+        let report = strategy
+            .align(
+                &base_lm,
+                "",
+                "Capital of France?",
+                "London",
+                &BaseGeneration::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(report.response(), Some("Paris"));
+
+        // Each correction is shown along with the response it refers to.
+        let conversations = base_lm.conversations.lock().unwrap();
+        let correction_prompt = &conversations[1][0].content;
+        assert!(correction_prompt.contains("PREVIOUS RESPONSE: Lyon"));
+        assert!(correction_prompt.contains("CORRECTION: Not Lyon"));
+        assert!(!correction_prompt.contains("London"));
+    }
 
-        let executor: StructuredExecutor<AlignmentResponse> = StructuredExecutorBuilder::new()
-            .with_lm(self.lm)
-            .with_preamble(&preamble)
-            .with_options(Box::new(variants!(AlignmentResponse)))
+    #[tokio::test]
+    async fn test_align_regeneration_options() {
+        let base_lm = ScriptedLanguageModel::new(&["Paris"]);
+        let judge_lm = ScriptedLanguageModel::new(&[
+            r#"{"response_type": "ResponseCorrection", "correction": "Not London", "reason": "Wrong capital"}"#,
+            r#"{"response_type": "NoCorrection", "reason": "Paris is correct"}"#,
+        ]);
+        let strategy = AlignmentStrategyBuilder::new()
+            .with_lm(&judge_lm)
             .try_build()
             .unwrap();
-        let response = {
-            let correction_response = executor.execute(original_prompt).await;
-
-            match correction_response {
-                Ok(response) => Some(response.content),
-                Err(ExecutorError::Parsing(_)) => {
-                    // The model failed to parse the response, so we return the original response.
-                    return Ok(None);
-                }
-                Err(e) => return Err(AlignmentError::ExecutionFailed(e.to_string())),
-            }
+        let base_generation = BaseGeneration {
+            generation: GenerationOptions {
+                temperature: Some(0.2),
+                max_tokens: Some(64),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        strategy
+            .align(
+                &base_lm,
+                "",
+                "Capital of France?",
+                "London",
+                &base_generation,
+            )
+            .await
+            .unwrap();
+
+        // The re-generation keeps the sampling parameters of the original generation.
+        let generations = base_lm.generations.lock().unwrap();
+        assert_eq!(generations.len(), 1);
+        assert_eq!(generations[0].temperature, Some(0.2));
+        assert_eq!(generations[0].max_tokens, Some(64));
+    }
+
+    #[tokio::test]
+    async fn test_align_judge_unparseable() {
+        let base_lm = ScriptedLanguageModel::new(&[]);
+        let judge_lm = ScriptedLanguageModel::new(&[
+            r#"{"response_type": "Fail", "reason": "The question is vague"}"#,
+            "Looks good to me!",
+            "Looks good to me!",
+        ]);
+        let strategy = AlignmentStrategyBuilder::new()
+            .with_lm(&judge_lm)
+            .with_max_judge_calls(3)
+            .try_build()
+            .unwrap();
+
+        let result = strategy
+            .align(
+                &base_lm,
+                "",
+                "Capital?",
+                "Paris",
+                &BaseGeneration::default(),
+            )
+            .await;
+        let Err(AlignmentError::JudgeUnparseable { attempts, report }) = result else {
+            panic!("Expected the judge responses to be unparseable");
         };
+        assert_eq!(attempts, 2);
+        assert_eq!(report.iterations.len(), 3);
+        assert_eq!(report.iterations[0].verdict, AlignmentVerdict::Fail);
+    }
+
+    #[tokio::test]
+    async fn test_align_judges() {
+        let base_lm = ScriptedLanguageModel::new(&["{\"capital\": \"Paris\"}"]);
+        let judge_lm = ScriptedLanguageModel::new(&[
+            r#"{"response_type": "NoCorrection", "reason": "Paris is correct"}"#,
+        ]);
+        let strategy = AlignmentStrategyBuilder::new()
+            .with_judge(JsonSchemaJudge::new(serde_json::json!({
+                "type": "object",
+                "properties": { "capital": { "type": "string" } },
+                "required": ["capital"]
+            })))
+            .with_judge(FnJudge::new("capitalized", |response: &str| {
+                if response.contains("\"paris\"") {
+                    Err("The capital should be capitalized".to_string())
+                } else {
+                    Ok(())
+                }
+            }))
+            .with_lm(&judge_lm)
+            .try_build()
+            .unwrap();
+
+        let report = strategy
+            .align(
+                &base_lm,
+                "",
+                "Capital of France?",
+                "{\"capital\": \"paris\"}",
+                &BaseGeneration::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(report.response(), Some("{\"capital\": \"Paris\"}"));
+        let judges = report
+            .iterations
+            .iter()
+            .map(|iteration| iteration.judge.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(judges, ["capitalized", "lm:scripted"]);
+        assert_eq!(
+            report.iterations[0].verdict,
+            AlignmentVerdict::ResponseCorrection("The capital should be capitalized".to_string())
+        );
+        // The deterministic judges reject the response without calling the language model.
+        assert_eq!(judge_lm.conversations.lock().unwrap().len(), 1);
+    }
 
-        Ok(response)
+    #[tokio::test]
+    async fn test_align_no_judges() {
+        let result = AlignmentStrategyBuilder::new().try_build();
+        assert!(result.is_err());
     }
 }
@@ -1,22 +1,33 @@
+use std::time::Duration;
+
 use thiserror::Error;
 
 use crate::lm::LanguageModel;
 
-use super::strategy::AlignmentStrategy;
+use super::{strategy::AlignmentStrategy, AlignmentJudge, LanguageModelJudge};
 
 /// The default number of retries for the alignment strategy, if not overriden.
 pub const DEFAULT_RETRIES: usize = 2;
 
+/// The default maximum number of calls to the judge model, if not overriden.
+pub const DEFAULT_MAX_JUDGE_CALLS: usize = 5;
+
 #[derive(Debug, Error)]
 pub enum AlignmentStrategyBuilderError {
     #[error("{0} is not set")]
     ConfigurationNotSet(String),
+
+    #[error("Invalid configuration: {0}")]
+    InvalidConfiguration(String),
 }
 
 #[derive(Default)]
 pub struct AlignmentStrategyBuilder<'a> {
     lm: Option<&'a dyn LanguageModel>,
+    judges: Vec<Box<dyn AlignmentJudge + 'a>>,
     retries: Option<usize>,
+    max_judge_calls: usize,
+    timeout: Option<Duration>,
 }
 
 impl<'a> AlignmentStrategyBuilder<'a> {
@@ -24,35 +35,73 @@ impl<'a> AlignmentStrategyBuilder<'a> {
     pub fn new() -> Self {
         Self {
             lm: None,
+            judges: Vec::new(),
             retries: Some(DEFAULT_RETRIES),
+            max_judge_calls: DEFAULT_MAX_JUDGE_CALLS,
+            timeout: None,
         }
     }
 
-    /// Sets the language model to use for the alignment strategy.
+    /// Sets the language model to use as a judge for the alignment strategy.
+    /// The language model judge reviews the candidate after all other judges (see [`Self::with_judge`]).
     pub fn with_lm(mut self, lm: &'a dyn LanguageModel) -> Self {
         self.lm = Some(lm);
         self
     }
 
-    /// Sets the number of retries for the alignment strategy.
+    /// Adds a judge to the alignment strategy (e.g., a [`super::JsonSchemaJudge`] or a [`super::FnJudge`]).
+    /// Judges review each candidate in the order they were added, and the first correction is used.
+    pub fn with_judge(mut self, judge: impl AlignmentJudge + 'a) -> Self {
+        self.judges.push(Box::new(judge));
+        self
+    }
+
+    /// Sets the number of retries for the alignment strategy
+    /// (i.e., how many times the base model may re-generate its response after a correction).
     pub fn with_retries(mut self, retries: usize) -> Self {
         self.retries = Some(retries);
         self
     }
 
+    /// Sets the maximum number of calls to the judge model, including calls whose response could not be parsed.
+    pub fn with_max_judge_calls(mut self, max_judge_calls: usize) -> Self {
+        self.max_judge_calls = max_judge_calls;
+        self
+    }
+
+    /// Sets the maximum wall-clock time of the alignment (no timeout by default).
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
     /// Builds the alignment strategy.
     /// May fail with a [`AlignmentStrategyBuilderErrro`] if some required configurations are not set.
     pub fn try_build(self) -> Result<AlignmentStrategy<'a>, AlignmentStrategyBuilderError> {
-        let Some(lm) = self.lm else {
+        let mut judges = self.judges;
+        if let Some(lm) = self.lm {
+            judges.push(Box::new(LanguageModelJudge::new(lm)));
+        }
+        if judges.is_empty() {
             return Err(AlignmentStrategyBuilderError::ConfigurationNotSet(
-                "Language model".to_string(),
+                "Language model or judge".to_string(),
             ));
-        };
+        }
         let Some(retries) = self.retries else {
             return Err(AlignmentStrategyBuilderError::ConfigurationNotSet(
                 "Retries".to_string(),
             ));
         };
-        Ok(AlignmentStrategy { lm, retries })
+        if self.max_judge_calls == 0 {
+            return Err(AlignmentStrategyBuilderError::InvalidConfiguration(
+                "The judge model must be called at least once".to_string(),
+            ));
+        }
+        Ok(AlignmentStrategy {
+            judges,
+            retries,
+            max_judge_calls: self.max_judge_calls,
+            timeout: self.timeout,
+        })
     }
 }
@@ -0,0 +1,322 @@
+use std::{fmt::Display, future::Future, pin::Pin};
+
+use crate::lm::{
+    ChatMessage, FinishReason, GenerationOptions, LanguageModel, TextCompleteOptions, TokenUsage,
+    Tool, ToolCall,
+};
+
+use super::{ExecutorBuilderError, ExecutorError, DEFAULT_PREAMBLE};
+
+/// Default maximum number of model calls an [`AgentExecutor`] performs before giving up.
+pub const DEFAULT_MAX_STEPS: usize = 10;
+
+type ToolHandler<'a> = Box<
+    dyn Fn(serde_json::Value) -> Pin<Box<dyn Future<Output = Result<String, String>> + Send + 'a>>
+        + Send
+        + Sync
+        + 'a,
+>;
+
+/// A [`Tool`] registered in an [`AgentExecutor`], along with the function that runs it.
+pub struct AgentTool<'a> {
+    pub(crate) tool: Tool,
+    pub(crate) handler: ToolHandler<'a>,
+}
+
+/// An executor which runs an agent loop: it repeatedly calls the model, runs the tools it requests
+/// and feeds their results back, until the model responds with a final answer.
+pub struct AgentExecutor<'a> {
+    pub(crate) lm: &'a dyn LanguageModel,
+    pub(crate) preamble: Option<&'a str>,
+    pub(crate) generation_options: GenerationOptions,
+    pub(crate) tools: Vec<AgentTool<'a>>,
+    pub(crate) max_steps: usize,
+}
+
+/// Response of an [`AgentExecutor`].
+#[derive(Debug)]
+pub struct AgentExecutorResponse {
+    /// The final answer of the model.
+    pub content: String,
+    /// The full conversation, including the tool calls and their results.
+    pub messages: Vec<ChatMessage>,
+    /// Number of model calls which were performed.
+    pub steps: usize,
+    /// Accumulated token usage of all model calls, if reported by the provider.
+    pub usage: Option<TokenUsage>,
+    /// The reason the model stopped generating the final answer, if reported by the provider.
+    pub finish_reason: Option<FinishReason>,
+}
+
+impl<'a> AgentExecutor<'a> {
+    /// Runs the agent loop for a prompt.
+    ///
+    /// # Arguments
+    /// * `prompt` - The task for the agent.
+    ///
+    /// # Returns
+    /// A [Result] containing the final answer and the conversation, or an error if there was a problem
+    /// (including [`ExecutorError::MaxStepsExceeded`] if no final answer was given within the step budget).
+    pub async fn execute(&self, prompt: &str) -> Result<AgentExecutorResponse, ExecutorError> {
+        let messages = vec![
+            ChatMessage::system(self.preamble.unwrap_or(DEFAULT_PREAMBLE)),
+            ChatMessage::user(prompt),
+        ];
+        self.execute_messages(messages).await
+    }
+
+    /// Runs the agent loop, continuing an existing conversation.
+    ///
+    /// # Arguments
+    /// * `messages` - The conversation so far (including an optional system message).
+    ///
+    /// # Returns
+    /// A [Result] containing the final answer and the conversation, or an error if there was a problem.
+    pub async fn execute_messages(
+        &self,
+        mut messages: Vec<ChatMessage>,
+    ) -> Result<AgentExecutorResponse, ExecutorError> {
+        let tools = self
+            .tools
+            .iter()
+            .map(|agent_tool| agent_tool.tool.clone())
+            .collect::<Vec<_>>();
+        let mut usage: Option<TokenUsage> = None;
+
+        for step in 1..=self.max_steps {
+            let options = TextCompleteOptions {
+                generation: self.generation_options.clone(),
+                tools: tools.clone(),
+                ..Default::default()
+            };
+            let response = self
+                .lm
+                .chat_complete(&messages, options)
+                .await
+                .map_err(ExecutorError::from)?;
+            if let Some(step_usage) = response.usage {
+                let usage = usage.get_or_insert_with(TokenUsage::default);
+                usage.prompt_tokens += step_usage.prompt_tokens;
+                usage.completion_tokens += step_usage.completion_tokens;
+            }
+
+            if response.tool_calls.is_empty() {
+                messages.push(ChatMessage::assistant(response.text.clone()));
+                return Ok(AgentExecutorResponse {
+                    content: response.text,
+                    messages,
+                    steps: step,
+                    usage,
+                    finish_reason: response.finish_reason,
+                });
+            }
+
+            messages.push(ChatMessage::assistant_tool_calls(
+                response.text,
+                response.tool_calls.clone(),
+            ));
+            for tool_call in response.tool_calls {
+                let result = self.run_tool(&tool_call).await;
+                messages.push(ChatMessage::tool_result(tool_call.id, result));
+            }
+        }
+
+        Err(ExecutorError::MaxStepsExceeded(self.max_steps))
+    }
+
+    /// Runs a tool call. Failures are reported back to the model (rather than aborting the loop),
+    /// so that it has a chance to correct itself.
+    async fn run_tool(&self, tool_call: &ToolCall) -> String {
+        let Some(agent_tool) = self
+            .tools
+            .iter()
+            .find(|agent_tool| agent_tool.tool.name == tool_call.name)
+        else {
+            return format!("Error: tool '{}' does not exist", tool_call.name);
+        };
+        match (agent_tool.handler)(tool_call.arguments.clone()).await {
+            Ok(result) => result,
+            Err(e) => format!("Error: {e}"),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct AgentExecutorBuilder<'a> {
+    lm: Option<&'a dyn LanguageModel>,
+    preamble: Option<&'a str>,
+    generation_options: GenerationOptions,
+    tools: Vec<AgentTool<'a>>,
+    max_steps: Option<usize>,
+}
+
+impl<'a> AgentExecutorBuilder<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_lm(mut self, lm: &'a dyn LanguageModel) -> Self {
+        self.lm = Some(lm);
+        self
+    }
+
+    pub fn with_preamble(mut self, preamble: &'a str) -> Self {
+        self.preamble = Some(preamble);
+        self
+    }
+
+    /// Registers a tool which the agent may use.
+    ///
+    /// # Arguments
+    /// * `tool` - The definition of the tool (as exposed to the model).
+    /// * `handler` - An async function which receives the arguments of the tool call and returns its result.
+    ///   Errors are reported back to the model.
+    pub fn with_tool<F, Fut, E>(mut self, tool: Tool, handler: F) -> Self
+    where
+        F: Fn(serde_json::Value) -> Fut + Send + Sync + 'a,
+        Fut: Future<Output = Result<String, E>> + Send + 'a,
+        E: Display,
+    {
+        let handler: ToolHandler<'a> = Box::new(move |arguments| {
+            let result = handler(arguments);
+            Box::pin(async move { result.await.map_err(|e| e.to_string()) })
+        });
+        self.tools.push(AgentTool { tool, handler });
+        self
+    }
+
+    /// Sets the maximum number of model calls before giving up.
+    /// Defaults to [`DEFAULT_MAX_STEPS`].
+    pub fn with_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = Some(max_steps);
+        self
+    }
+
+    /// Sets the temperature (amount of randomness) of the generation.
+    pub fn with_temperature(mut self, temperature: f32) -> Self {
+        self.generation_options.temperature = Some(temperature);
+        self
+    }
+
+    /// Sets the maximum number of tokens to generate (per step).
+    pub fn with_max_tokens(mut self, max_tokens: usize) -> Self {
+        self.generation_options.max_tokens = Some(max_tokens);
+        self
+    }
+
+    /// Sets all sampling parameters of the generation at once.
+    pub fn with_generation_options(mut self, generation_options: GenerationOptions) -> Self {
+        self.generation_options = generation_options;
+        self
+    }
+
+    pub fn try_build(self) -> Result<AgentExecutor<'a>, ExecutorBuilderError> {
+        let Some(lm) = self.lm else {
+            return Err(ExecutorBuilderError::ConfigurationNotSet(
+                "Language model".to_string(),
+            ));
+        };
+        for (i, agent_tool) in self.tools.iter().enumerate() {
+            if self.tools[..i]
+                .iter()
+                .any(|other| other.tool.name == agent_tool.tool.name)
+            {
+                return Err(ExecutorBuilderError::InvalidConfiguration(format!(
+                    "Tool '{}' is registered more than once",
+                    agent_tool.tool.name
+                )));
+            }
+        }
+        let max_steps = self.max_steps.unwrap_or(DEFAULT_MAX_STEPS);
+        if max_steps == 0 {
+            return Err(ExecutorBuilderError::InvalidConfiguration(
+                "Maximum number of steps must be greater than 0".to_string(),
+            ));
+        }
+        Ok(AgentExecutor {
+            lm,
+            preamble: self.preamble,
+            generation_options: self.generation_options,
+            tools: self.tools,
+            max_steps,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::{scripted_response as response, ScriptedLanguageModel};
+
+    use super::*;
+
+    fn add_tool_call(id: &str, tool: &str) -> ToolCall {
+        ToolCall {
+            id: id.to_string(),
+            name: tool.to_string(),
+            arguments: serde_json::json!({ "a": 2, "b": 3 }),
+        }
+    }
+
+    async fn add(arguments: serde_json::Value) -> Result<String, String> {
+        let a = arguments["a"].as_i64().ok_or("Missing 'a'")?;
+        let b = arguments["b"].as_i64().ok_or("Missing 'b'")?;
+        Ok((a + b).to_string())
+    }
+
+    fn add_tool() -> Tool {
+        Tool::new(
+            "add",
+            "Adds two numbers",
+            serde_json::json!({ "type": "object" }),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_agent_runs_tools_until_final_answer() {
+        let lm = ScriptedLanguageModel::default();
+        *lm.responses.lock().unwrap() = vec![
+            response(
+                "",
+                vec![add_tool_call("1", "add"), add_tool_call("2", "subtract")],
+            ),
+            response("The answer is 5", Vec::new()),
+        ];
+        let executor = AgentExecutorBuilder::new()
+            .with_lm(&lm)
+            .with_tool(add_tool(), add)
+            .try_build()
+            .unwrap();
+
+        let response = executor.execute("What is 2+3?").await.unwrap();
+        assert_eq!(response.content, "The answer is 5");
+        assert_eq!(response.steps, 2);
+        assert_eq!(response.usage.unwrap().prompt_tokens, 20);
+
+        let conversations = lm.conversations.lock().unwrap();
+        let second_call = &conversations[1];
+        assert_eq!(second_call[2].tool_calls.len(), 2);
+        assert_eq!(second_call[3], ChatMessage::tool_result("1", "5"));
+        assert_eq!(
+            second_call[4],
+            ChatMessage::tool_result("2", "Error: tool 'subtract' does not exist")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_agent_step_budget() {
+        let lm = ScriptedLanguageModel::default();
+        *lm.responses.lock().unwrap() = vec![
+            response("", vec![add_tool_call("1", "add")]),
+            response("", vec![add_tool_call("2", "add")]),
+        ];
+        let executor = AgentExecutorBuilder::new()
+            .with_lm(&lm)
+            .with_tool(add_tool(), add)
+            .with_max_steps(2)
+            .try_build()
+            .unwrap();
+
+        let result = executor.execute("What is 2+3?").await;
+        assert!(matches!(result, Err(ExecutorError::MaxStepsExceeded(2))));
+    }
+}
@@ -6,4 +6,6 @@ pub enum ExecutorBuilderError {
     InternalError(String),
     #[error("{0} is not set")]
     ConfigurationNotSet(String),
+    #[error("Invalid configuration: {0}")]
+    InvalidConfiguration(String),
 }
@@ -1,11 +1,17 @@
-use std::pin::Pin;
+use std::{
+    pin::Pin,
+    sync::{Arc, Mutex},
+};
 
 use thiserror::Error;
 use tokio_stream::Stream;
 
 use crate::{
-    alignment::AlignmentError,
-    lm::{LanguageModel, LanguageModelError, OllamaError, TextCompleteOptions},
+    alignment::{AlignmentError, AlignmentReport},
+    lm::{
+        ChatMessage, FinishReason, GenerationOptions, LanguageModel, LanguageModelError,
+        OllamaError, ResponseFormat, TextCompleteOptions, TextCompleteStreamMetadata, TokenUsage,
+    },
 };
 
 #[derive(Debug, Error)]
@@ -24,6 +30,9 @@ pub enum ExecutorError {
 
     #[error("Alignment error: {0}")]
     Alignment(AlignmentError),
+
+    #[error("No final answer was produced within {0} steps")]
+    MaxStepsExceeded(usize),
 }
 
 impl From<LanguageModelError> for ExecutorError {
@@ -36,53 +45,176 @@ impl From<LanguageModelError> for ExecutorError {
 }
 
 pub(crate) trait Executor<'a> {
-    /// Generates a text completion from the LLM (non-streaming).
+    /// Generates a text completion from the LLM (non-streaming), continuing the conversation in `context`.
     async fn text_complete(
         &self,
         prompt: &str,
+        context: &ExecutorContext,
     ) -> Result<ExecutorTextCompleteResponse<String>, ExecutorError> {
-        text_complete(self.lm(), prompt, &self.system_prompt()).await
+        text_complete(
+            self.lm(),
+            prompt,
+            &self.system_prompt(),
+            &self.generation_options(),
+            &self.response_format(),
+            context,
+        )
+        .await
     }
 
     /// System prompt (instructions) for the model.
     fn system_prompt(&self) -> String;
 
+    /// Sampling parameters (e.g., temperature) for the model.
+    fn generation_options(&self) -> GenerationOptions;
+
+    /// Format of the response to request from the provider.
+    fn response_format(&self) -> ResponseFormat {
+        ResponseFormat::Text
+    }
+
     fn lm(&self) -> &'a dyn LanguageModel;
 }
 
-// TODO: Support context for completions (e.g., IDs of past conversations in Ollama).
-pub struct ExecutorContext;
+/// State of a conversation with a model, which can be passed to a subsequent execution
+/// to continue a multi-turn session.
+///
+/// The conversation is kept as context tokens if the model returned them (Ollama),
+/// otherwise as the accumulated message history (e.g., OpenAI, Anthropic).
+#[derive(Debug, Clone, Default)]
+pub struct ExecutorContext {
+    /// Context tokens returned by the model, which encode the conversation so far (Ollama only).
+    pub tokens: Option<Vec<i64>>,
+    /// Messages of the conversation so far (excluding the system prompt).
+    pub messages: Vec<ChatMessage>,
+}
+
+impl ExecutorContext {
+    /// Creates an empty context (i.e., a new conversation).
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether the context is of a new conversation.
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_none() && self.messages.is_empty()
+    }
+
+    /// Returns a new context with an additional turn of the conversation.
+    pub(crate) fn with_turn(
+        &self,
+        prompt: &str,
+        response: &str,
+        tokens: Option<Vec<i64>>,
+    ) -> ExecutorContext {
+        let mut messages = self.messages.clone();
+        messages.push(ChatMessage::user(prompt));
+        messages.push(ChatMessage::assistant(response));
+        ExecutorContext { tokens, messages }
+    }
+}
 
 pub struct ExecutorTextCompleteResponse<T> {
     pub content: T,
+    /// Conversation state including this response, to be passed to the next execution.
     pub context: ExecutorContext,
+    /// Token usage of the generation, if reported by the provider.
+    pub usage: Option<TokenUsage>,
+    /// The reason the model stopped generating, if reported by the provider.
+    pub finish_reason: Option<FinishReason>,
+    /// Attempts to parse the response of the model, in order (empty for text responses).
+    pub parse_attempts: Vec<ParseAttempt>,
+    /// Details of the execution (e.g., the prompts and the raw response), for debugging.
+    pub trace: ExecutionTrace,
+}
+
+/// Details of an execution, for debugging.
+#[derive(Debug, Clone, Default)]
+pub struct ExecutionTrace {
+    /// The system prompt sent to the model.
+    pub system_prompt: String,
+    /// The prompt sent to the model.
+    pub prompt: String,
+    /// The text of the first response of the model (before alignment, repairs and parse retries).
+    pub raw_response: String,
+    /// Report of the alignment (e.g., the corrections of the judge), if an alignment strategy was used.
+    pub alignment: Option<AlignmentReport>,
+    /// The discriminator (`response_type`) of the variant the model responded with (`None` for text responses).
+    pub response_type: Option<String>,
+}
+
+/// An attempt to parse the response of the model into a structured response.
+#[derive(Debug, Clone)]
+pub struct ParseAttempt {
+    /// The response of the model (after repairs, if enabled).
+    pub response: String,
+    /// The parsing error, if the attempt failed.
+    pub error: Option<String>,
 }
 
 pub struct ExecutorTextCompleteStreamResponse {
     pub stream: Pin<Box<dyn Stream<Item = Result<String, LanguageModelError>> + Send>>,
-    pub context: ExecutorContext,
+    /// Metadata of the response (e.g., token usage), which is available once the stream has been fully consumed.
+    pub metadata: TextCompleteStreamMetadata,
+    pub(crate) context: Arc<Mutex<Option<ExecutorContext>>>,
 }
 
-pub async fn text_complete<'a>(
-    lm: &'a dyn LanguageModel,
+impl ExecutorTextCompleteStreamResponse {
+    /// Returns the conversation state including this response, to be passed to the next execution,
+    /// once the stream has been fully consumed (`None` before that, or if the stream failed).
+    pub fn context(&self) -> Option<ExecutorContext> {
+        self.context.lock().unwrap().clone()
+    }
+}
+
+pub async fn text_complete(
+    lm: &dyn LanguageModel,
     prompt: &str,
     system_prompt: &str,
+    generation: &GenerationOptions,
+    response_format: &ResponseFormat,
+    context: &ExecutorContext,
 ) -> Result<ExecutorTextCompleteResponse<String>, ExecutorError> {
-    let options = TextCompleteOptions {
-        ..Default::default()
-    };
-    let response = lm
-        .text_complete(prompt, system_prompt, options)
-        .await
-        .map_err(ExecutorError::from)?;
+    // The conversation is kept in the context tokens if the model returned them (Ollama), otherwise
+    // the messages are sent (e.g., when an Ollama model fell back to another provider).
+    let response = if context.tokens.is_some() || context.messages.is_empty() {
+        let options = TextCompleteOptions {
+            context: context.tokens.clone(),
+            generation: generation.clone(),
+            response_format: response_format.clone(),
+            ..Default::default()
+        };
+        lm.text_complete(prompt, system_prompt, options).await
+    } else {
+        let messages = std::iter::once(ChatMessage::system(system_prompt))
+            .chain(context.messages.iter().cloned())
+            .chain(std::iter::once(ChatMessage::user(prompt)))
+            .collect::<Vec<_>>();
+        let options = TextCompleteOptions {
+            generation: generation.clone(),
+            response_format: response_format.clone(),
+            ..Default::default()
+        };
+        lm.chat_complete(&messages, options).await
+    }
+    .map_err(ExecutorError::from)?;
     Ok(ExecutorTextCompleteResponse {
+        trace: ExecutionTrace {
+            system_prompt: system_prompt.to_string(),
+            prompt: prompt.to_string(),
+            raw_response: response.text.clone(),
+            ..Default::default()
+        },
+        context: context.with_turn(prompt, &response.text, response.context),
         content: response.text,
-        context: ExecutorContext {},
+        usage: response.usage,
+        finish_reason: response.finish_reason,
+        parse_attempts: Vec::new(),
     })
 }
 
-pub(crate) async fn generate_embedding<'a>(
-    lm: &'a dyn LanguageModel,
+pub(crate) async fn generate_embedding(
+    lm: &dyn LanguageModel,
     prompt: &str,
 ) -> Result<Vec<f32>, ExecutorError> {
     let response = lm
@@ -5,14 +5,18 @@
 //! It is not to be confused with an [`Orchestrator`] which manages the execution of an LLM
 //! or multiple LLMs towards a task.
 
+mod agent_executor;
 mod builder;
 mod executor;
 mod response;
+mod self_consistency;
 mod structured_executor;
 mod text_executor;
 
+pub use agent_executor::*;
 pub use builder::*;
 pub use executor::*;
 pub use response::*;
+pub use self_consistency::*;
 pub use structured_executor::*;
 pub use text_executor::*;
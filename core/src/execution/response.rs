@@ -1,11 +1,2 @@
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum ResponseFormat {
-    Text,
-    Json,
-}
-
-impl Default for ResponseFormat {
-    fn default() -> Self {
-        Self::Text
-    }
-}
+/// Re-exported for backwards compatibility, as the response format is now part of the completion options.
+pub use crate::lm::ResponseFormat;
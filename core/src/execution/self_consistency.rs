@@ -0,0 +1,397 @@
+use futures::future::join_all;
+
+use crate::lm::TokenUsage;
+
+use super::{
+    text_complete, Executor, ExecutorContext, ExecutorError, ParseAttempt, StructuredExecutor,
+};
+
+/// The default number of samples for self-consistency execution, if not overriden.
+pub const DEFAULT_SELF_CONSISTENCY_SAMPLES: usize = 5;
+
+/// Options of a self-consistency execution (see [`StructuredExecutor::execute_self_consistent`]).
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelfConsistencyOptions {
+    /// Number of responses to sample.
+    pub samples: usize,
+    /// Maximum number of samples to generate at the same time.
+    pub concurrency: usize,
+    /// Temperature of the samples, overriding the temperature of the executor.
+    pub temperature: Option<f32>,
+}
+
+impl Default for SelfConsistencyOptions {
+    fn default() -> Self {
+        Self::new(DEFAULT_SELF_CONSISTENCY_SAMPLES)
+    }
+}
+
+impl SelfConsistencyOptions {
+    /// Creates options which sample `samples` responses (at least one), one at a time.
+    pub fn new(samples: usize) -> Self {
+        Self {
+            samples: samples.max(1),
+            concurrency: 1,
+            temperature: None,
+        }
+    }
+
+    /// Sets the maximum number of samples to generate at the same time.
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Sets the temperature of the samples (which should be high enough for the samples to differ).
+    pub fn with_temperature(mut self, temperature: f32) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+}
+
+/// The majority response of a self-consistency execution.
+pub struct ExecutorSelfConsistencyResponse<T> {
+    /// The response most samples agree on.
+    pub content: T,
+    /// Number of samples which agree with the majority response.
+    pub votes: usize,
+    /// Fraction of the samples which agree with the majority response (between 0 and 1),
+    /// where samples which could not be generated or parsed count as disagreeing.
+    pub agreement: f32,
+    /// The samples, in order (including the ones which could not be generated or parsed).
+    pub samples: Vec<ParseAttempt>,
+    /// Total token usage of the samples, if reported by the provider.
+    pub usage: Option<TokenUsage>,
+}
+
+impl<'a, T> StructuredExecutor<'a, T> {
+    /// Samples the same prompt several times and returns the response most samples agree on
+    /// (self-consistency), which is useful for classification with small models.
+    ///
+    /// Samples which cannot be generated (e.g., a request failed) or parsed do not vote.
+    /// On a tie, the response sampled first wins.
+    /// Alignment and parse retries are not applied to the samples.
+    ///
+    /// # Arguments
+    /// * `prompt` - The prompt to generate responses for.
+    /// * `options` - The number of samples, their concurrency and temperature.
+    ///
+    /// # Returns
+    /// A [Result] containing the majority response or an error if there was a problem
+    /// (i.e., if none of the samples could be generated or parsed).
+    pub async fn execute_self_consistent(
+        &'a self,
+        prompt: &'a str,
+        options: &SelfConsistencyOptions,
+    ) -> Result<ExecutorSelfConsistencyResponse<T>, ExecutorError>
+    where
+        T: PartialEq,
+    {
+        let (samples, answers, usage) = self.sample(prompt, options).await?;
+        let majority = majority(&answers);
+        Self::majority_response(samples, answers, usage, majority)
+    }
+
+    /// Same as [`Self::execute_self_consistent`], but groups the responses by a key
+    /// (e.g., only the label of a classification, ignoring the explanation).
+    pub async fn execute_self_consistent_by_key<K, F>(
+        &'a self,
+        prompt: &'a str,
+        options: &SelfConsistencyOptions,
+        key: F,
+    ) -> Result<ExecutorSelfConsistencyResponse<T>, ExecutorError>
+    where
+        K: PartialEq,
+        F: Fn(&T) -> K,
+    {
+        let (samples, answers, usage) = self.sample(prompt, options).await?;
+        let keys = answers
+            .iter()
+            .map(|answer| answer.as_ref().map(&key))
+            .collect::<Vec<_>>();
+        let majority = majority(&keys);
+        Self::majority_response(samples, answers, usage, majority)
+    }
+
+    /// Generates the samples, and parses each of them.
+    ///
+    /// Fails only if none of the samples could be generated (with the error of the last one).
+    async fn sample(
+        &self,
+        prompt: &str,
+        options: &SelfConsistencyOptions,
+    ) -> Result<(Vec<ParseAttempt>, Vec<Option<T>>, Option<TokenUsage>), ExecutorError> {
+        let mut generation = self.generation_options.clone();
+        if let Some(temperature) = options.temperature {
+            generation.temperature = Some(temperature);
+        }
+        // A fixed seed would make all the samples identical, so each sample gets its own seed.
+        let generations = (0..options.samples)
+            .map(|i| {
+                let mut generation = generation.clone();
+                generation.seed = generation.seed.map(|seed| seed.wrapping_add(i as u64));
+                generation
+            })
+            .collect::<Vec<_>>();
+        let system_prompt = self.system_prompt();
+        let response_format = self.response_format();
+        let context = ExecutorContext::new();
+
+        let mut responses = Vec::with_capacity(options.samples);
+        for batch in generations.chunks(options.concurrency.max(1)) {
+            let futures = batch.iter().map(|generation| {
+                text_complete(
+                    self.lm,
+                    prompt,
+                    &system_prompt,
+                    generation,
+                    &response_format,
+                    &context,
+                )
+            });
+            responses.extend(join_all(futures).await);
+        }
+
+        let mut samples = Vec::with_capacity(responses.len());
+        let mut answers = Vec::with_capacity(responses.len());
+        let mut usage: Option<TokenUsage> = None;
+        let mut generated = 0;
+        let mut last_error = None;
+        for response in responses {
+            let response = match response {
+                Ok(response) => response,
+                Err(error) => {
+                    samples.push(ParseAttempt {
+                        response: String::new(),
+                        error: Some(error.to_string()),
+                    });
+                    answers.push(None);
+                    last_error = Some(error);
+                    continue;
+                }
+            };
+            generated += 1;
+            if let Some(sample_usage) = response.usage {
+                let usage = usage.get_or_insert_with(TokenUsage::default);
+                usage.prompt_tokens += sample_usage.prompt_tokens;
+                usage.completion_tokens += sample_usage.completion_tokens;
+            }
+            let (parsed_response, result) = self.parse_response(&response.content);
+            samples.push(ParseAttempt {
+                response: parsed_response,
+                error: result.as_ref().err().cloned(),
+            });
+            answers.push(result.ok());
+        }
+        match last_error {
+            Some(error) if generated == 0 => Err(error),
+            _ => Ok((samples, answers, usage)),
+        }
+    }
+
+    /// Builds the response from the majority answer (see [`majority`]).
+    fn majority_response(
+        samples: Vec<ParseAttempt>,
+        mut answers: Vec<Option<T>>,
+        usage: Option<TokenUsage>,
+        majority: Option<(usize, usize)>,
+    ) -> Result<ExecutorSelfConsistencyResponse<T>, ExecutorError> {
+        let Some((index, votes)) = majority else {
+            let error = samples
+                .last()
+                .and_then(|sample| sample.error.clone())
+                .unwrap_or_default();
+            return Err(ExecutorError::Parsing(format!(
+                "None of the {} samples could be parsed, the last error: {error}",
+                samples.len()
+            )));
+        };
+        let content = answers[index]
+            .take()
+            .expect("The majority answer should be parsed");
+        Ok(ExecutorSelfConsistencyResponse {
+            content,
+            votes,
+            agreement: votes as f32 / samples.len() as f32,
+            samples,
+            usage,
+        })
+    }
+}
+
+/// Groups the (parsed) answers by equality, and returns the index of the first answer
+/// of the largest group along with the size of the group (the earliest group wins a tie).
+fn majority<K: PartialEq>(keys: &[Option<K>]) -> Option<(usize, usize)> {
+    let mut majority: Option<(usize, usize)> = None;
+    for (i, key) in keys.iter().enumerate() {
+        let Some(key) = key else {
+            continue;
+        };
+        if keys[..i].iter().any(|other| other.as_ref() == Some(key)) {
+            // Already counted with the first answer of its group.
+            continue;
+        }
+        let votes = keys[i..]
+            .iter()
+            .filter(|other| other.as_ref() == Some(key))
+            .count();
+        if majority.map_or(true, |(_, majority_votes)| votes > majority_votes) {
+            majority = Some((i, votes));
+        }
+    }
+    majority
+}
+
+#[cfg(test)]
+mod tests {
+    use orch_response_derive::{variants, Variant, Variants};
+    use serde::Deserialize;
+
+    use crate::{
+        execution::StructuredExecutorBuilder,
+        lm::{LanguageModelError, OllamaError},
+        test_utils::{FailingLanguageModel, ScriptedLanguageModel},
+    };
+
+    use super::*;
+
+    #[derive(Variants, Deserialize, Debug, PartialEq)]
+    pub enum SentimentResponse {
+        Answer(AnswerVariant),
+    }
+
+    #[derive(Variant, Deserialize, Debug, PartialEq)]
+    #[variant(
+        variant = "Answer",
+        scenario = "You know the sentiment",
+        description = "Sentiment of the text"
+    )]
+    pub struct AnswerVariant {
+        #[schema(description = "Sentiment of the text", example = "positive")]
+        pub sentiment: String,
+        #[schema(description = "Short explanation", example = "The text is cheerful")]
+        pub reason: String,
+    }
+
+    fn answer(sentiment: &str, reason: &str) -> String {
+        format!(
+            r#"{{"response_type": "Answer", "sentiment": "{sentiment}", "reason": "{reason}"}}"#
+        )
+    }
+
+    #[tokio::test]
+    async fn test_execute_self_consistent() {
+        let lm = ScriptedLanguageModel::new(&[
+            &answer("negative", "Sad"),
+            &answer("positive", "Happy"),
+            "Positive!",
+            &answer("positive", "Happy"),
+        ]);
+        let executor = StructuredExecutorBuilder::new()
+            .with_lm(&lm)
+            .with_options(Box::new(variants!(SentimentResponse)))
+            .with_seed(7)
+            .try_build()
+            .unwrap();
+
+        let options = SelfConsistencyOptions::new(4)
+            .with_concurrency(3)
+            .with_temperature(0.8);
+        let response = executor
+            .execute_self_consistent("I love it", &options)
+            .await
+            .unwrap();
+        let SentimentResponse::Answer(answer) = response.content;
+        assert_eq!(answer.sentiment, "positive");
+        assert_eq!(response.votes, 2);
+        assert_eq!(response.agreement, 0.5);
+        assert_eq!(response.samples.len(), 4);
+        assert!(response.samples[2].error.is_some());
+        assert_eq!(response.usage.unwrap().completion_tokens, 20);
+
+        let generations = lm.generations.lock().unwrap();
+        assert_eq!(generations.len(), 4);
+        assert!(generations
+            .iter()
+            .all(|generation| generation.temperature == Some(0.8)));
+        let seeds = generations
+            .iter()
+            .map(|generation| generation.seed)
+            .collect::<Vec<_>>();
+        assert_eq!(seeds, [Some(7), Some(8), Some(9), Some(10)]);
+    }
+
+    fn unavailable() -> LanguageModelError {
+        LanguageModelError::Ollama(OllamaError::ApiUnavailable(
+            "Connection refused".to_string(),
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_execute_self_consistent_failed_samples() {
+        let lm = FailingLanguageModel::new(unavailable).with_failures(
+            1,
+            ScriptedLanguageModel::new(&[
+                &answer("positive", "Happy"),
+                &answer("positive", "Happy"),
+            ]),
+        );
+        let executor = StructuredExecutorBuilder::new()
+            .with_lm(&lm)
+            .with_options(Box::new(variants!(SentimentResponse)))
+            .try_build()
+            .unwrap();
+
+        let response = executor
+            .execute_self_consistent("I love it", &SelfConsistencyOptions::new(3))
+            .await
+            .unwrap();
+        assert_eq!(response.votes, 2);
+        assert_eq!(response.samples.len(), 3);
+        assert!(response.samples[0]
+            .error
+            .as_ref()
+            .is_some_and(|error| error.contains("Connection refused")));
+    }
+
+    #[tokio::test]
+    async fn test_execute_self_consistent_all_samples_failed() {
+        let lm = FailingLanguageModel::new(unavailable);
+        let executor = StructuredExecutorBuilder::new()
+            .with_lm(&lm)
+            .with_options(Box::new(variants!(SentimentResponse)))
+            .try_build()
+            .unwrap();
+
+        let result = executor
+            .execute_self_consistent("I love it", &SelfConsistencyOptions::new(3))
+            .await;
+        assert!(matches!(result, Err(ExecutorError::LanguageModelError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_execute_self_consistent_by_key() {
+        let lm = ScriptedLanguageModel::new(&[
+            &answer("negative", "Sad"),
+            &answer("positive", "Happy"),
+            &answer("positive", "Cheerful"),
+        ]);
+        let executor = StructuredExecutorBuilder::new()
+            .with_lm(&lm)
+            .with_options(Box::new(variants!(SentimentResponse)))
+            .try_build()
+            .unwrap();
+
+        let response = executor
+            .execute_self_consistent_by_key(
+                "I love it",
+                &SelfConsistencyOptions::new(3),
+                |SentimentResponse::Answer(answer)| answer.sentiment.clone(),
+            )
+            .await
+            .unwrap();
+        let SentimentResponse::Answer(answer) = response.content;
+        assert_eq!(answer.reason, "Happy");
+        assert_eq!(response.votes, 2);
+    }
+}
@@ -1,12 +1,22 @@
-use std::cell::OnceCell;
+use std::{cell::OnceCell, pin::Pin};
 
-use orch_response::{OrchResponseVariants, ResponseSchemaField};
+use async_gen::AsyncIter;
+use orch_response::{
+    parse_partial_json, repair_json, OrchResponseVariants, ResponseSchemaField, RESPONSE_TYPE_FIELD,
+};
+use tokio_stream::{Stream, StreamExt};
 
-use crate::{alignment::AlignmentStrategy, lm::LanguageModel};
+use crate::{
+    alignment::{AlignmentStrategy, BaseGeneration},
+    lm::{
+        GenerationOptions, LanguageModel, ResponseFormat, TextCompleteStreamMetadata,
+        TextCompleteStreamOptions, TokenUsage,
+    },
+};
 
 use super::{
     generate_embedding, Executor, ExecutorBuilderError, ExecutorContext, ExecutorError,
-    ExecutorTextCompleteResponse, DEFAULT_PREAMBLE,
+    ExecutorTextCompleteResponse, ParseAttempt,
 };
 
 pub struct StructuredExecutor<'a, T> {
@@ -14,6 +24,10 @@ pub struct StructuredExecutor<'a, T> {
     pub(crate) preamble: Option<&'a str>,
     pub(crate) variants: Box<dyn OrchResponseVariants<T>>,
     pub(crate) alignment_strategy: Option<AlignmentStrategy<'a>>,
+    pub(crate) generation_options: GenerationOptions,
+    pub(crate) response_format: ResponseFormat,
+    pub(crate) json_repair: bool,
+    pub(crate) max_parse_retries: usize,
 }
 
 impl<'a, T> Executor<'a> for StructuredExecutor<'a, T> {
@@ -21,6 +35,14 @@ impl<'a, T> Executor<'a> for StructuredExecutor<'a, T> {
         self.lm
     }
 
+    fn generation_options(&self) -> GenerationOptions {
+        self.generation_options.clone()
+    }
+
+    fn response_format(&self) -> ResponseFormat {
+        self.response_format.clone()
+    }
+
     fn system_prompt(&self) -> String {
         let cell = OnceCell::new();
 
@@ -38,7 +60,7 @@ impl<'a, T> Executor<'a> for StructuredExecutor<'a, T> {
                     let mut schema_example = "{".to_string();
                     let type_field = ResponseSchemaField {
                         // NOTE: This is assumed by [`orch_response_derive`] to be the discriminator field.
-                        name: "response_type".to_string(),
+                        name: RESPONSE_TYPE_FIELD.to_string(),
                         description: format!(
                             "The type of the response (\"{}\" in this case)",
                             option.type_name
@@ -46,25 +68,22 @@ impl<'a, T> Executor<'a> for StructuredExecutor<'a, T> {
                         .to_string(),
                         typ: "string".to_string(),
                         example: all_types.first().unwrap().to_string(),
+                        schema: serde_json::Value::Null,
+                        optional: false,
                     };
 
-                    for (i, field) in option
-                        .schema
-                        .iter()
-                        .chain(std::iter::once(&type_field))
-                        .enumerate()
-                    {
+                    let mut example_fields = Vec::new();
+                    for field in option.schema.iter().chain(std::iter::once(&type_field)) {
+                        let optional = if field.optional { ", optional" } else { "" };
                         schema_text.push_str(&format!(
-                            "  - `{}` of type {} (description: {})\n\n",
-                            field.name, field.typ, field.description
+                            "  - `{}` of type {}{} (description: {})\n\n",
+                            field.name, field.typ, optional, field.description
                         ));
-                        schema_example
-                            .push_str(&format!("\"{}\": \"{}\"", field.name, field.example));
-
-                        if i < option.schema.len() - 1 {
-                            schema_example.push(',');
-                        }
+                        // Examples of non-string fields (e.g., numbers or objects) are embedded as JSON.
+                        let example = &field.json_schema()["examples"][0];
+                        example_fields.push(format!("\"{}\": {}", field.name, example));
                     }
+                    schema_example.push_str(&example_fields.join(","));
                     schema_example.push('}');
 
                     format!(
@@ -100,6 +119,23 @@ impl<'a, T> Executor<'a> for StructuredExecutor<'a, T> {
     }
 }
 
+/// An event of a streaming structured response (see [`StructuredExecutor::execute_stream`]).
+#[derive(Debug, Clone)]
+pub enum StructuredStreamEvent<T> {
+    /// The response parsed so far, which may be missing fields or have incomplete values
+    /// (e.g., a string which is still being generated).
+    Partial(serde_json::Value),
+    /// The complete response, parsed into one of the response variants.
+    Done(T),
+}
+
+pub struct ExecutorStructuredStreamResponse<'a, T> {
+    pub stream:
+        Pin<Box<dyn Stream<Item = Result<StructuredStreamEvent<T>, ExecutorError>> + Send + 'a>>,
+    /// Metadata of the response (e.g., token usage), which is available once the stream has been fully consumed.
+    pub metadata: TextCompleteStreamMetadata,
+}
+
 /// Trait for LLM execution.
 /// This should be implemented for each LLM text generation use-case, where the system prompt
 /// changes according to the trait implementations.
@@ -116,29 +152,211 @@ impl<'a, T> StructuredExecutor<'a, T> {
         &'a self,
         prompt: &'a str,
     ) -> Result<ExecutorTextCompleteResponse<T>, ExecutorError> {
-        let mut model_response = self.text_complete(prompt).await?.content;
+        self.execute_with_context(prompt, &ExecutorContext::default())
+            .await
+    }
+
+    /// Generates a structured response from the LLM (streaming).
+    ///
+    /// The stream yields the partial response parsed so far whenever it changes, followed by the final response.
+    /// Alignment and parse retries are not applied to streaming responses.
+    ///
+    /// # Arguments
+    /// * `prompt` - The prompt to generate a response for.
+    ///
+    /// # Returns
+    /// A [Result] containing the stream of the response or an error if there was a problem.
+    pub async fn execute_stream(
+        &'a self,
+        prompt: &'a str,
+    ) -> Result<ExecutorStructuredStreamResponse<'a, T>, ExecutorError>
+    where
+        T: Send + Sync + 'a,
+    {
+        let options = TextCompleteStreamOptions {
+            generation: self.generation_options.clone(),
+            response_format: self.response_format(),
+            ..Default::default()
+        };
+        let system_prompt = self.system_prompt();
+        let response = self
+            .lm
+            .text_complete_stream(prompt, &system_prompt, options)
+            .await
+            .map_err(ExecutorError::from)?;
+        let mut chunks = response.stream;
+        let stream = AsyncIter::from(async_gen::gen! {
+            let mut buffer = String::new();
+            let mut partial = None;
+            while let Some(chunk) = chunks.next().await {
+                match chunk {
+                    Ok(chunk) => buffer.push_str(&chunk),
+                    Err(e) => {
+                        yield Err(ExecutorError::from(e));
+                        return;
+                    }
+                }
+                let current = parse_partial_json(&buffer);
+                if current.is_some() && current != partial {
+                    partial = current;
+                    yield Ok(StructuredStreamEvent::Partial(partial.clone().unwrap()));
+                }
+            }
+            match self.parse_response(&buffer).1 {
+                Ok(result) => {
+                    yield Ok(StructuredStreamEvent::Done(result));
+                }
+                Err(error) => {
+                    yield Err(ExecutorError::Parsing(error));
+                }
+            }
+        });
+        Ok(ExecutorStructuredStreamResponse {
+            stream: Box::pin(stream),
+            metadata: response.metadata,
+        })
+    }
+
+    /// Generates a structured response from the LLM (non-streaming), continuing a previous conversation.
+    ///
+    /// # Arguments
+    /// * `prompt` - The prompt to generate a response for.
+    /// * `context` - The context of the previous response (see [`ExecutorTextCompleteResponse::context`]).
+    ///
+    /// # Returns
+    /// A [Result] containing the response from the LLM (with the updated context) or an error if there was a problem.
+    pub async fn execute_with_context(
+        &'a self,
+        prompt: &'a str,
+        context: &ExecutorContext,
+    ) -> Result<ExecutorTextCompleteResponse<T>, ExecutorError> {
+        let mut response = self.text_complete(prompt, context).await?;
+        let mut model_response = response.content;
         if let Some(alignment_strategy) = &self.alignment_strategy {
-            model_response = alignment_strategy
+            let report = alignment_strategy
                 .align(
                     self.lm,
-                    self.preamble.unwrap_or(DEFAULT_PREAMBLE),
+                    &self.system_prompt(),
                     prompt,
                     &model_response,
+                    &BaseGeneration {
+                        generation: self.generation_options.clone(),
+                        response_format: self.response_format(),
+                        context: context.clone(),
+                    },
                 )
                 .await
                 .map_err(ExecutorError::Alignment)?;
+            if let Some(aligned_response) = report.response() {
+                model_response = aligned_response.to_owned();
+            }
+            // Continue the conversation from the aligned response.
+            if let Some(message) = response.context.messages.last_mut() {
+                message.content = model_response.clone();
+            }
+            response.trace.alignment = Some(report);
         }
-        let result = self
-            .variants
-            .parse(&model_response)
-            .map_err(|e| ExecutorError::Parsing(format!("{e}\nResponse: {:?}", model_response)))?;
-        // TODO: Add error correction and handling.
+
+        let mut trace = response.trace;
+        let mut context = response.context;
+        let mut usage = response.usage;
+        let mut finish_reason = response.finish_reason;
+        let mut parse_attempts = Vec::new();
+        let result = loop {
+            let (parsed_response, result) = self.parse_response(&model_response);
+            let error = match result {
+                Ok(result) => {
+                    trace.response_type =
+                        serde_json::from_str::<serde_json::Value>(&parsed_response)
+                            .ok()
+                            .and_then(|response| {
+                                response[RESPONSE_TYPE_FIELD]
+                                    .as_str()
+                                    .map(ToString::to_string)
+                            });
+                    parse_attempts.push(ParseAttempt {
+                        response: parsed_response,
+                        error: None,
+                    });
+                    break result;
+                }
+                Err(error) => error,
+            };
+            parse_attempts.push(ParseAttempt {
+                response: parsed_response,
+                error: Some(error.clone()),
+            });
+            if parse_attempts.len() > self.max_parse_retries {
+                let attempts = if parse_attempts.len() > 1 {
+                    format!("\n(after {} attempts)", parse_attempts.len())
+                } else {
+                    String::new()
+                };
+                return Err(ExecutorError::Parsing(format!("{error}{attempts}")));
+            }
+
+            // Ask the model to correct its response, continuing the conversation.
+            let correction_prompt = format!(
+                "Your response could not be parsed: {error}\n\nRespond again with *only* a JSON object which follows one of the schemas above."
+            );
+            let response = self.text_complete(&correction_prompt, &context).await?;
+            if let Some(attempt_usage) = response.usage {
+                let usage = usage.get_or_insert_with(TokenUsage::default);
+                usage.prompt_tokens += attempt_usage.prompt_tokens;
+                usage.completion_tokens += attempt_usage.completion_tokens;
+            }
+            model_response = response.content;
+            context = response.context;
+            finish_reason = response.finish_reason;
+        };
         Ok(ExecutorTextCompleteResponse {
             content: result,
-            context: ExecutorContext {},
+            context,
+            usage,
+            finish_reason,
+            parse_attempts,
+            trace,
         })
     }
 
+    /// Parses a response of the model into one of the response variants.
+    ///
+    /// Returns the parsed (possibly repaired) response, and the result of parsing it,
+    /// where a parsing error explains which part of the response is invalid.
+    pub(crate) fn parse_response(&self, model_response: &str) -> (String, Result<T, String>) {
+        // Small models often wrap the JSON in code fences or explanations, or make syntax errors.
+        let (model_response, repairs) = if self.json_repair {
+            let repaired = repair_json(model_response);
+            (repaired.json, repaired.repairs)
+        } else {
+            (model_response.to_string(), Vec::new())
+        };
+        let result = self.variants.parse(&model_response).map_err(|e| {
+            // Explain which part of the response does not match the schema, if it is valid JSON.
+            let validation_errors = serde_json::from_str(&model_response)
+                .ok()
+                .and_then(|response| self.variants.validate(&response).err())
+                .map(|errors| {
+                    errors
+                        .iter()
+                        .map(|error| format!("\n- {error}"))
+                        .collect::<String>()
+                })
+                .unwrap_or_default();
+            let repairs = if repairs.is_empty() {
+                String::new()
+            } else {
+                let repairs = repairs.iter().map(ToString::to_string).collect::<Vec<_>>();
+                format!("\nRepairs: {}", repairs.join(", "))
+            };
+            format!(
+                "{e}{validation_errors}{repairs}\nResponse: {:?}",
+                model_response
+            )
+        });
+        (model_response, result)
+    }
+
     /// Generates an embedding from the LLM.
     ///
     /// # Arguments
@@ -158,6 +376,11 @@ pub struct StructuredExecutorBuilder<'a, T> {
     preamble: Option<&'a str>,
     variants: Option<Box<dyn OrchResponseVariants<T>>>,
     alignment_strategy: Option<AlignmentStrategy<'a>>,
+    generation_options: GenerationOptions,
+    response_format: Option<ResponseFormat>,
+    json_schema: bool,
+    json_repair: bool,
+    max_parse_retries: usize,
 }
 
 impl<'a, T> StructuredExecutorBuilder<'a, T> {
@@ -167,6 +390,11 @@ impl<'a, T> StructuredExecutorBuilder<'a, T> {
             preamble: None,
             variants: None,
             alignment_strategy: None,
+            generation_options: GenerationOptions::default(),
+            response_format: None,
+            json_schema: false,
+            json_repair: false,
+            max_parse_retries: 0,
         }
     }
 
@@ -190,6 +418,72 @@ impl<'a, T> StructuredExecutorBuilder<'a, T> {
         self
     }
 
+    /// Sets the temperature (amount of randomness) of the generation.
+    pub fn with_temperature(mut self, temperature: f32) -> Self {
+        self.generation_options.temperature = Some(temperature);
+        self
+    }
+
+    /// Sets the maximum number of tokens to generate.
+    pub fn with_max_tokens(mut self, max_tokens: usize) -> Self {
+        self.generation_options.max_tokens = Some(max_tokens);
+        self
+    }
+
+    /// Sets the top p (nucleus sampling) of the generation.
+    pub fn with_top_p(mut self, top_p: f32) -> Self {
+        self.generation_options.top_p = Some(top_p);
+        self
+    }
+
+    /// Sets the sequences which cause the model to stop generating.
+    pub fn with_stop(mut self, stop: Vec<String>) -> Self {
+        self.generation_options.stop = Some(stop);
+        self
+    }
+
+    /// Sets the seed for (best effort) deterministic generation.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.generation_options.seed = Some(seed);
+        self
+    }
+
+    /// Sets all sampling parameters of the generation at once.
+    pub fn with_generation_options(mut self, generation_options: GenerationOptions) -> Self {
+        self.generation_options = generation_options;
+        self
+    }
+
+    /// Sets the format of the response requested from the provider.
+    /// Defaults to [`ResponseFormat::Json`] (use [`ResponseFormat::Text`] to rely only on the instructions in the system prompt).
+    pub fn with_response_format(mut self, response_format: ResponseFormat) -> Self {
+        self.response_format = Some(response_format);
+        self
+    }
+
+    /// Sets whether to request a response which matches the JSON Schema of the response variants
+    /// (i.e., [`ResponseFormat::JsonSchema`]), overriding [`Self::with_response_format`]. Disabled by default.
+    ///
+    /// Providers fall back to JSON mode for schemas (or models) they do not support.
+    pub fn with_json_schema(mut self, json_schema: bool) -> Self {
+        self.json_schema = json_schema;
+        self
+    }
+
+    /// Sets whether to extract and repair the JSON in the response before parsing it
+    /// (e.g., strip Markdown code fences and remove trailing commas). Disabled by default.
+    pub fn with_json_repair(mut self, json_repair: bool) -> Self {
+        self.json_repair = json_repair;
+        self
+    }
+
+    /// Sets the maximum number of times to ask the model to correct a response which cannot be parsed,
+    /// by sending it the parsing error. Defaults to 0 (no retries).
+    pub fn with_max_parse_retries(mut self, max_parse_retries: usize) -> Self {
+        self.max_parse_retries = max_parse_retries;
+        self
+    }
+
     pub fn try_build(self) -> Result<StructuredExecutor<'a, T>, ExecutorBuilderError> {
         let Some(lm) = self.lm else {
             return Err(ExecutorBuilderError::ConfigurationNotSet(
@@ -201,11 +495,155 @@ impl<'a, T> StructuredExecutorBuilder<'a, T> {
                 "Response variants are not set".to_string(),
             ));
         };
+        let response_format = if self.json_schema {
+            ResponseFormat::JsonSchema(response_options.json_schema())
+        } else {
+            self.response_format.unwrap_or(ResponseFormat::Json)
+        };
         Ok(StructuredExecutor {
             lm,
             preamble: self.preamble,
             variants: response_options,
             alignment_strategy: self.alignment_strategy,
+            generation_options: self.generation_options,
+            response_format,
+            json_repair: self.json_repair,
+            max_parse_retries: self.max_parse_retries,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use orch_response_derive::{variants, Variant, Variants};
+    use serde::Deserialize;
+
+    use crate::test_utils::ScriptedLanguageModel;
+
+    use super::*;
+
+    #[derive(Variants, Deserialize, Debug)]
+    pub enum CapitalResponse {
+        Answer(AnswerVariant),
+    }
+
+    #[derive(Variant, Deserialize, Debug)]
+    #[variant(
+        variant = "Answer",
+        scenario = "You know the answer",
+        description = "Capital city"
+    )]
+    pub struct AnswerVariant {
+        #[schema(description = "Capital city", example = "London")]
+        pub capital: String,
+    }
+
+    #[test]
+    fn test_response_format() {
+        let lm = ScriptedLanguageModel::new(&[]);
+        let executor = StructuredExecutorBuilder::new()
+            .with_lm(&lm)
+            .with_options(Box::new(variants!(CapitalResponse)))
+            .try_build()
+            .unwrap();
+        assert_eq!(executor.response_format(), ResponseFormat::Json);
+
+        let executor = StructuredExecutorBuilder::new()
+            .with_lm(&lm)
+            .with_options(Box::new(variants!(CapitalResponse)))
+            .with_json_schema(true)
+            .try_build()
+            .unwrap();
+        assert_eq!(
+            executor.response_format(),
+            ResponseFormat::JsonSchema(variants!(CapitalResponse).json_schema())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_parse_retries() {
+        let lm = ScriptedLanguageModel::new(&[
+            "The capital is Paris",
+            r#"{"response_type": "Answer", "capital": "Paris"}"#,
+        ]);
+        let executor = StructuredExecutorBuilder::new()
+            .with_lm(&lm)
+            .with_options(Box::new(variants!(CapitalResponse)))
+            .with_max_parse_retries(1)
+            .try_build()
+            .unwrap();
+
+        let response = executor.execute("Capital of France?").await.unwrap();
+        let CapitalResponse::Answer(answer) = response.content;
+        assert_eq!(answer.capital, "Paris");
+        assert_eq!(response.parse_attempts.len(), 2);
+        assert!(response.parse_attempts[0].error.is_some());
+        assert!(response.parse_attempts[1].error.is_none());
+        assert_eq!(response.usage.unwrap().completion_tokens, 10);
+        assert_eq!(response.trace.raw_response, "The capital is Paris");
+        assert_eq!(response.trace.prompt, "Capital of France?");
+        assert_eq!(response.trace.response_type.as_deref(), Some("Answer"));
+        assert!(response
+            .trace
+            .system_prompt
+            .contains("SCENARIO: You know the answer"));
+
+        // The retry continues the conversation with the unparseable response.
+        let conversations = lm.conversations.lock().unwrap();
+        assert_eq!(conversations[1].len(), 4);
+        assert_eq!(conversations[1][2].content, "The capital is Paris");
+        assert!(conversations[1][3]
+            .content
+            .starts_with("Your response could not be parsed: expected value"));
+    }
+
+    #[tokio::test]
+    async fn test_execute_stream() {
+        let lm =
+            ScriptedLanguageModel::new(&[r#"{"response_type": "Answer", "capital": "Paris"}"#]);
+        let executor = StructuredExecutorBuilder::new()
+            .with_lm(&lm)
+            .with_options(Box::new(variants!(CapitalResponse)))
+            .try_build()
+            .unwrap();
+
+        let mut response = executor.execute_stream("Capital of France?").await.unwrap();
+        let mut partials = Vec::new();
+        let mut done = None;
+        while let Some(event) = response.stream.next().await {
+            match event.unwrap() {
+                StructuredStreamEvent::Partial(partial) => partials.push(partial),
+                StructuredStreamEvent::Done(result) => done = Some(result),
+            }
+        }
+        // The capital is streamed before it is complete.
+        assert!(partials.iter().any(|partial| partial["capital"]
+            .as_str()
+            .is_some_and(|capital| capital.len() < 5)));
+        assert_eq!(
+            partials.last(),
+            Some(&serde_json::json!({ "response_type": "Answer", "capital": "Paris" }))
+        );
+        let Some(CapitalResponse::Answer(answer)) = done else {
+            panic!("Expected a final response");
+        };
+        assert_eq!(answer.capital, "Paris");
+    }
+
+    #[tokio::test]
+    async fn test_parse_retries_exhausted() {
+        let lm = ScriptedLanguageModel::new(&["Paris", "```Paris```"]);
+        let executor = StructuredExecutorBuilder::new()
+            .with_lm(&lm)
+            .with_options(Box::new(variants!(CapitalResponse)))
+            .with_max_parse_retries(1)
+            .try_build()
+            .unwrap();
+
+        let result = executor.execute("Capital of France?").await;
+        let Err(ExecutorError::Parsing(error)) = result else {
+            panic!("Expected a parsing error");
+        };
+        assert!(error.ends_with("(after 2 attempts)"));
+    }
+}
@@ -1,4 +1,9 @@
-use crate::lm::{LanguageModel, TextCompleteStreamOptions};
+use std::sync::{Arc, Mutex};
+
+use async_gen::AsyncIter;
+use tokio_stream::StreamExt;
+
+use crate::lm::{ChatMessage, GenerationOptions, LanguageModel, TextCompleteStreamOptions};
 
 use super::{
     generate_embedding, Executor, ExecutorBuilderError, ExecutorContext, ExecutorError,
@@ -10,6 +15,7 @@ pub const DEFAULT_PREAMBLE: &str = "You are a helpful assistant";
 pub struct TextExecutor<'a> {
     pub(crate) lm: &'a dyn LanguageModel,
     pub(crate) preamble: Option<&'a str>,
+    pub(crate) generation_options: GenerationOptions,
 }
 
 impl<'a> Executor<'a> for TextExecutor<'a> {
@@ -20,6 +26,10 @@ impl<'a> Executor<'a> for TextExecutor<'a> {
     fn system_prompt(&self) -> String {
         self.preamble.unwrap_or(DEFAULT_PREAMBLE).to_owned()
     }
+
+    fn generation_options(&self) -> GenerationOptions {
+        self.generation_options.clone()
+    }
 }
 
 /// Trait for LLM execution.
@@ -30,26 +40,77 @@ impl<'a> TextExecutor<'a> {
     ///
     /// # Arguments
     /// * `prompt` - The prompt to generate a response for.
-    /// * `system_prompt` - The system prompt to use for the generation.
     ///
     /// # Returns
     /// A [Result] containing the response from the LLM or an error if there was a problem.
     pub async fn execute_stream(
         &'a self,
         prompt: &'a str,
+    ) -> Result<ExecutorTextCompleteStreamResponse, ExecutorError> {
+        self.execute_stream_with_context(prompt, &ExecutorContext::default())
+            .await
+    }
+
+    /// Generates a streaming response from the LLM, continuing a previous conversation.
+    ///
+    /// The context tokens are sent if there are any (Ollama), otherwise the previous messages are sent
+    /// as a transcript (see [`ChatMessage::transcript`]).
+    ///
+    /// # Arguments
+    /// * `prompt` - The prompt to generate a response for.
+    /// * `context` - The context of the previous response (see [`ExecutorTextCompleteStreamResponse::context`]).
+    ///
+    /// # Returns
+    /// A [Result] containing the response from the LLM or an error if there was a problem.
+    /// The updated context is available once the stream has been fully consumed.
+    pub async fn execute_stream_with_context(
+        &'a self,
+        prompt: &'a str,
+        context: &ExecutorContext,
     ) -> Result<ExecutorTextCompleteStreamResponse, ExecutorError> {
         let options = TextCompleteStreamOptions {
+            context: context.tokens.clone(),
+            generation: self.generation_options.clone(),
             ..Default::default()
         };
+        let full_prompt = if context.tokens.is_some() {
+            prompt.to_owned()
+        } else {
+            let mut messages = context.messages.clone();
+            messages.push(ChatMessage::user(prompt));
+            ChatMessage::transcript(&messages).1
+        };
         let system_prompt = self.system_prompt();
-        let response = self
+        let mut response = self
             .lm
-            .text_complete_stream(prompt, &system_prompt, options)
+            .text_complete_stream(&full_prompt, &system_prompt, options)
             .await
             .map_err(ExecutorError::General)?;
+
+        // Records the updated context once the stream has been fully consumed.
+        let updated_context = Arc::new(Mutex::new(None));
+        let stream_context = updated_context.clone();
+        let metadata = response.metadata.clone();
+        let context = context.clone();
+        let prompt = prompt.to_owned();
+        let stream = AsyncIter::from(async_gen::gen! {
+            let mut text = String::new();
+            while let Some(chunk) = response.stream.next().await {
+                let failed = chunk.is_err();
+                if let Ok(chunk) = &chunk {
+                    text.push_str(chunk);
+                }
+                yield chunk;
+                if failed {
+                    return;
+                }
+            }
+            *stream_context.lock().unwrap() = Some(context.with_turn(&prompt, &text, metadata.context()));
+        });
         Ok(ExecutorTextCompleteStreamResponse {
-            stream: response.stream,
-            context: ExecutorContext {},
+            stream: Box::pin(stream),
+            metadata: response.metadata,
+            context: updated_context,
         })
     }
 
@@ -65,7 +126,24 @@ impl<'a> TextExecutor<'a> {
         &'a self,
         prompt: &'a str,
     ) -> Result<ExecutorTextCompleteResponse<String>, ExecutorError> {
-        self.text_complete(prompt).await
+        self.text_complete(prompt, &ExecutorContext::default())
+            .await
+    }
+
+    /// Generates a response from the LLM (non-streaming), continuing a previous conversation.
+    ///
+    /// # Arguments
+    /// * `prompt` - The prompt to generate a response for.
+    /// * `context` - The context of the previous response (see [`ExecutorTextCompleteResponse::context`]).
+    ///
+    /// # Returns
+    /// A [Result] containing the response from the LLM (with the updated context) or an error if there was a problem.
+    pub async fn execute_with_context(
+        &'a self,
+        prompt: &'a str,
+        context: &ExecutorContext,
+    ) -> Result<ExecutorTextCompleteResponse<String>, ExecutorError> {
+        self.text_complete(prompt, context).await
     }
 
     /// Generates an embedding from the LLM.
@@ -85,6 +163,7 @@ impl<'a> TextExecutor<'a> {
 pub struct TextExecutorBuilder<'a> {
     lm: Option<&'a dyn LanguageModel>,
     preamble: Option<&'a str>,
+    generation_options: GenerationOptions,
 }
 
 impl<'a> TextExecutorBuilder<'a> {
@@ -92,6 +171,7 @@ impl<'a> TextExecutorBuilder<'a> {
         Self {
             lm: None,
             preamble: None,
+            generation_options: GenerationOptions::default(),
         }
     }
 
@@ -105,6 +185,42 @@ impl<'a> TextExecutorBuilder<'a> {
         self
     }
 
+    /// Sets the temperature (amount of randomness) of the generation.
+    pub fn with_temperature(mut self, temperature: f32) -> Self {
+        self.generation_options.temperature = Some(temperature);
+        self
+    }
+
+    /// Sets the maximum number of tokens to generate.
+    pub fn with_max_tokens(mut self, max_tokens: usize) -> Self {
+        self.generation_options.max_tokens = Some(max_tokens);
+        self
+    }
+
+    /// Sets the top p (nucleus sampling) of the generation.
+    pub fn with_top_p(mut self, top_p: f32) -> Self {
+        self.generation_options.top_p = Some(top_p);
+        self
+    }
+
+    /// Sets the sequences which cause the model to stop generating.
+    pub fn with_stop(mut self, stop: Vec<String>) -> Self {
+        self.generation_options.stop = Some(stop);
+        self
+    }
+
+    /// Sets the seed for (best effort) deterministic generation.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.generation_options.seed = Some(seed);
+        self
+    }
+
+    /// Sets all sampling parameters of the generation at once.
+    pub fn with_generation_options(mut self, generation_options: GenerationOptions) -> Self {
+        self.generation_options = generation_options;
+        self
+    }
+
     pub fn try_build(self) -> Result<TextExecutor<'a>, ExecutorBuilderError> {
         let Some(lm) = self.lm else {
             return Err(ExecutorBuilderError::ConfigurationNotSet(
@@ -114,6 +230,88 @@ impl<'a> TextExecutorBuilder<'a> {
         Ok(TextExecutor {
             lm,
             preamble: self.preamble,
+            generation_options: self.generation_options,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        lm::{FallbackLanguageModelBuilder, LanguageModelBuilder, LanguageModelError, OllamaError},
+        test_utils::{FailingLanguageModel, ScriptedLanguageModel},
+    };
+
+    use super::*;
+
+    async fn consume(response: &mut ExecutorTextCompleteStreamResponse) -> String {
+        let mut text = String::new();
+        while let Some(chunk) = response.stream.next().await {
+            text.push_str(&chunk.unwrap());
+        }
+        text
+    }
+
+    #[tokio::test]
+    async fn test_execute_stream_with_context() {
+        let lm = ScriptedLanguageModel::new(&["Paris", "About 2 million"]);
+        let executor = TextExecutorBuilder::new().with_lm(&lm).try_build().unwrap();
+
+        let mut response = executor.execute_stream("Capital of France?").await.unwrap();
+        assert!(response.context().is_none());
+        assert_eq!(consume(&mut response).await, "Paris");
+        let context = response.context().unwrap();
+        assert_eq!(context.messages.len(), 2);
+
+        let mut response = executor
+            .execute_stream_with_context("Its population?", &context)
+            .await
+            .unwrap();
+        assert_eq!(consume(&mut response).await, "About 2 million");
+        assert_eq!(response.context().unwrap().messages.len(), 4);
+
+        let conversations = lm.conversations.lock().unwrap();
+        assert_eq!(
+            conversations[1][1].content,
+            "User: Capital of France?\n\nAssistant: Paris\n\nUser: Its population?"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_execute_with_context_after_fallback() {
+        let fallback = ScriptedLanguageModel::new(&["Paris", "About 2 million"]);
+        let lm = FallbackLanguageModelBuilder::new()
+            .with_lm(Box::new(FailingLanguageModel::new(|| {
+                LanguageModelError::Ollama(OllamaError::ApiUnavailable(
+                    "Connection refused".to_string(),
+                ))
+            })))
+            .with_lm(Box::new(fallback.clone()))
+            .try_build()
+            .unwrap();
+        let executor = TextExecutorBuilder::new().with_lm(&lm).try_build().unwrap();
+
+        let response = executor.execute("Capital of France?").await.unwrap();
+        let response = executor
+            .execute_with_context("Its population?", &response.context)
+            .await
+            .unwrap();
+        assert_eq!(response.content, "About 2 million");
+
+        // The fallback model (which returns no context tokens) receives the previous messages.
+        let conversations = fallback.conversations.lock().unwrap();
+        let messages = conversations[1]
+            .iter()
+            .map(|message| message.content.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            [
+                DEFAULT_PREAMBLE,
+                "Capital of France?",
+                "Paris",
+                "Its population?"
+            ]
+        );
+    }
+}
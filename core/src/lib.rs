@@ -5,3 +5,5 @@ pub mod execution;
 pub mod lm;
 mod net;
 pub mod response;
+#[cfg(test)]
+mod test_utils;
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use thiserror::Error;
 
 use super::{AnthropicError, LanguageModelProvider, OllamaError, OpenAiError};
@@ -18,12 +20,6 @@ impl std::fmt::Display for LanguageModelProvider {
     }
 }
 
-impl Default for LanguageModelProvider {
-    fn default() -> Self {
-        Self::Ollama
-    }
-}
-
 #[derive(Debug, Error)]
 pub enum LanguageModelError {
     #[error("Text generation error: {0}")]
@@ -47,3 +43,200 @@ pub enum LanguageModelError {
     #[error("Anthropic error: {0}")]
     Anthropic(#[from] AnthropicError),
 }
+
+/// Details of an error response (i.e., a response with an unsuccessful HTTP status) of the API of a provider.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ApiErrorDetails {
+    /// HTTP status of the response.
+    pub status: u16,
+    /// Error message of the provider (or the response body, if it has no error message).
+    pub message: String,
+    /// Type of the error, if reported by the provider (e.g., "rate_limit_error" or "invalid_request_error").
+    pub error_type: Option<String>,
+    /// Code of the error, if reported by the provider (e.g., "context_length_exceeded").
+    pub code: Option<String>,
+    /// ID of the request, if reported by the provider (useful when contacting its support).
+    pub request_id: Option<String>,
+    /// The time to wait before retrying, if requested by the provider (e.g., in a `retry-after` header).
+    pub retry_after: Option<Duration>,
+    /// The raw body of the response.
+    pub body: String,
+}
+
+impl std::fmt::Display for ApiErrorDetails {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "HTTP {}", self.status)?;
+        if let Some(error_type) = self.error_type.as_ref().or(self.code.as_ref()) {
+            write!(f, " ({error_type})")?;
+        }
+        write!(f, ": {}", self.message)?;
+        if let Some(request_id) = &self.request_id {
+            write!(f, " (request ID: {request_id})")?;
+        }
+        Ok(())
+    }
+}
+
+/// The kind of an error response of a provider, to handle errors without matching on messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiErrorKind {
+    /// The API key is missing or invalid.
+    Authentication,
+    /// The API key is not allowed to use the resource (e.g., the model).
+    PermissionDenied,
+    /// The resource (e.g., the model) does not exist.
+    NotFound,
+    /// The prompt (and the requested number of tokens) exceeds the context window of the model.
+    ContextLengthExceeded,
+    /// The content was refused by a content policy (or a safety system) of the provider.
+    ContentPolicy,
+    /// Too many requests were sent in a short time.
+    RateLimited,
+    /// The quota (e.g., the credit balance) of the account is used up.
+    QuotaExceeded,
+    /// The servers of the provider are temporarily overloaded.
+    Overloaded,
+    /// The request timed out.
+    Timeout,
+    /// The request is invalid (e.g., an unsupported parameter).
+    InvalidRequest,
+    /// An unexpected error on the side of the provider.
+    Server,
+    /// Any other error.
+    Other,
+}
+
+impl ApiErrorDetails {
+    /// Returns the kind of the error, based on the error type and code of the provider, or on the HTTP status.
+    pub fn kind(&self) -> ApiErrorKind {
+        let error_type = self.error_type.as_deref().unwrap_or_default();
+        let code = self.code.as_deref().unwrap_or_default();
+        let message = self.message.to_lowercase();
+        if code == "context_length_exceeded"
+            || message.contains("prompt is too long")
+            || message.contains("maximum context length")
+        {
+            ApiErrorKind::ContextLengthExceeded
+        } else if matches!(code, "content_policy_violation" | "content_filter") {
+            ApiErrorKind::ContentPolicy
+        } else if code == "insufficient_quota" || error_type == "insufficient_quota" {
+            ApiErrorKind::QuotaExceeded
+        } else if error_type == "overloaded_error" {
+            ApiErrorKind::Overloaded
+        } else {
+            match self.status {
+                401 => ApiErrorKind::Authentication,
+                403 => ApiErrorKind::PermissionDenied,
+                404 => ApiErrorKind::NotFound,
+                408 => ApiErrorKind::Timeout,
+                429 => ApiErrorKind::RateLimited,
+                503 | 529 => ApiErrorKind::Overloaded,
+                400 | 413 | 422 => ApiErrorKind::InvalidRequest,
+                500.. => ApiErrorKind::Server,
+                _ => ApiErrorKind::Other,
+            }
+        }
+    }
+
+    /// Returns whether the request may succeed if retried
+    /// (i.e., a timeout, rate limit or server error such as an overloaded server).
+    pub fn is_retryable(&self) -> bool {
+        self.kind() != ApiErrorKind::QuotaExceeded
+            && (matches!(self.status, 408 | 409 | 429) || self.status >= 500)
+    }
+}
+
+impl LanguageModelError {
+    /// Returns whether the API of the provider could not be reached (e.g., Ollama is not running).
+    pub fn is_unavailable(&self) -> bool {
+        matches!(
+            self,
+            LanguageModelError::Ollama(OllamaError::ApiUnavailable(_))
+                | LanguageModelError::OpenAi(OpenAiError::ApiUnavailable(_))
+                | LanguageModelError::Anthropic(AnthropicError::ApiUnavailable(_))
+        )
+    }
+
+    /// Returns whether the API of the provider returned an error (or an unexpected response).
+    pub fn is_api_error(&self) -> bool {
+        matches!(
+            self,
+            LanguageModelError::Ollama(
+                OllamaError::Api(_) | OllamaError::Http(_) | OllamaError::Parsing(_)
+            ) | LanguageModelError::OpenAi(OpenAiError::Api(_) | OpenAiError::Http(_))
+                | LanguageModelError::Anthropic(AnthropicError::Api(_) | AnthropicError::Http(_))
+        )
+    }
+
+    /// Returns the details of the error response of the provider, if the API responded with an error status.
+    pub fn api_error_details(&self) -> Option<&ApiErrorDetails> {
+        match self {
+            LanguageModelError::Ollama(OllamaError::Http(details))
+            | LanguageModelError::OpenAi(OpenAiError::Http(details))
+            | LanguageModelError::Anthropic(AnthropicError::Http(details)) => {
+                Some(details.as_ref())
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns whether the request may succeed if retried
+    /// (e.g., the provider could not be reached, or responded with a rate limit or server error).
+    pub fn is_retryable(&self) -> bool {
+        self.is_unavailable()
+            || self
+                .api_error_details()
+                .is_some_and(ApiErrorDetails::is_retryable)
+    }
+
+    /// Returns the time to wait before retrying, if requested by the provider.
+    pub fn retry_after(&self) -> Option<Duration> {
+        self.api_error_details()
+            .and_then(|details| details.retry_after)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_api_error_kind() {
+        let details = ApiErrorDetails {
+            status: 400,
+            message: "This model's maximum context length is 8192 tokens".to_string(),
+            error_type: Some("invalid_request_error".to_string()),
+            code: Some("context_length_exceeded".to_string()),
+            request_id: Some("req_123".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(details.kind(), ApiErrorKind::ContextLengthExceeded);
+        assert!(!details.is_retryable());
+        assert_eq!(
+            details.to_string(),
+            "HTTP 400 (invalid_request_error): This model's maximum context length is 8192 tokens (request ID: req_123)"
+        );
+
+        let quota = ApiErrorDetails {
+            status: 429,
+            error_type: Some("insufficient_quota".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(quota.kind(), ApiErrorKind::QuotaExceeded);
+        assert!(!quota.is_retryable());
+
+        let overloaded = ApiErrorDetails {
+            status: 529,
+            error_type: Some("overloaded_error".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(overloaded.kind(), ApiErrorKind::Overloaded);
+        assert!(overloaded.is_retryable());
+
+        let unauthorized = ApiErrorDetails {
+            status: 401,
+            ..Default::default()
+        };
+        assert_eq!(unauthorized.kind(), ApiErrorKind::Authentication);
+    }
+}
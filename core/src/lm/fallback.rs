@@ -0,0 +1,301 @@
+use std::sync::Arc;
+
+use super::{
+    ChatMessage, LanguageModel, LanguageModelBuilder, LanguageModelBuilderError,
+    LanguageModelError, LanguageModelProvider, ServingModel, TextCompleteOptions,
+    TextCompleteResponse, TextCompleteStreamOptions, TextCompleteStreamResponse,
+};
+use async_trait::async_trait;
+
+/// Which errors of a model cause a [`FallbackLanguageModel`] to fall back to the next model.
+#[derive(Clone, Default)]
+pub enum FallbackPolicy {
+    /// Fall back only if the API of the provider could not be reached (e.g., Ollama is not running).
+    Unavailable,
+    /// Fall back if the API of the provider could not be reached or returned an error.
+    #[default]
+    ApiErrors,
+    /// Fall back on any error (including configuration errors and unsupported features).
+    AnyError,
+    /// Fall back if the function returns `true` for the error.
+    Custom(Arc<dyn Fn(&LanguageModelError) -> bool + Send + Sync>),
+}
+
+impl std::fmt::Debug for FallbackPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FallbackPolicy::Unavailable => write!(f, "Unavailable"),
+            FallbackPolicy::ApiErrors => write!(f, "ApiErrors"),
+            FallbackPolicy::AnyError => write!(f, "AnyError"),
+            FallbackPolicy::Custom(_) => write!(f, "Custom"),
+        }
+    }
+}
+
+impl FallbackPolicy {
+    /// Returns whether the error should cause a fall back to the next model.
+    pub fn should_fall_back(&self, error: &LanguageModelError) -> bool {
+        match self {
+            FallbackPolicy::Unavailable => error.is_unavailable(),
+            FallbackPolicy::ApiErrors => error.is_unavailable() || error.is_api_error(),
+            FallbackPolicy::AnyError => true,
+            FallbackPolicy::Custom(should_fall_back) => should_fall_back(error),
+        }
+    }
+}
+
+/// A language model which tries a list of models in order, falling back to the next model
+/// when a model fails with an error which matches the [`FallbackPolicy`]
+/// (e.g., a local Ollama model with OpenAI as a fallback).
+///
+/// The model which served a response is reported in [`TextCompleteResponse::served_by`]
+/// (or [`super::TextCompleteStreamMetadata::served_by`] for streaming responses).
+///
+/// The provider and model names are those of the first (primary) model.
+/// Embeddings are only generated by the primary model, since embeddings of different models cannot be compared.
+#[derive(Clone)]
+pub struct FallbackLanguageModel {
+    models: Vec<Box<dyn LanguageModel>>,
+    policy: FallbackPolicy,
+}
+
+impl FallbackLanguageModel {
+    /// Returns the models, in the order they are tried.
+    pub fn models(&self) -> &[Box<dyn LanguageModel>] {
+        &self.models
+    }
+
+    fn primary(&self) -> &dyn LanguageModel {
+        // The builder ensures that there is at least one model.
+        self.models[0].as_ref()
+    }
+
+    /// Returns the error of the last model if it failed, or if the policy does not allow falling back.
+    fn check_fall_back(
+        &self,
+        index: usize,
+        error: LanguageModelError,
+    ) -> Result<(), LanguageModelError> {
+        if index + 1 < self.models.len() && self.policy.should_fall_back(&error) {
+            Ok(())
+        } else {
+            Err(error)
+        }
+    }
+}
+
+#[async_trait]
+impl LanguageModel for FallbackLanguageModel {
+    async fn text_complete(
+        &self,
+        prompt: &str,
+        system_prompt: &str,
+        options: TextCompleteOptions,
+    ) -> Result<TextCompleteResponse, LanguageModelError> {
+        for (i, model) in self.models.iter().enumerate() {
+            let mut options = options.clone();
+            if i > 0 {
+                // Context tokens are specific to the model which returned them.
+                options.context = None;
+            }
+            match model.text_complete(prompt, system_prompt, options).await {
+                Ok(mut response) => {
+                    response
+                        .served_by
+                        .get_or_insert_with(|| ServingModel::of(model.as_ref()));
+                    return Ok(response);
+                }
+                Err(e) => self.check_fall_back(i, e)?,
+            }
+        }
+        unreachable!("The last model either succeeds or returns its error")
+    }
+
+    /// Falls back if opening the stream, or its first item, fails.
+    /// Errors after the stream has started are returned as is.
+    async fn text_complete_stream(
+        &self,
+        prompt: &str,
+        system_prompt: &str,
+        options: TextCompleteStreamOptions,
+    ) -> Result<TextCompleteStreamResponse, LanguageModelError> {
+        for (i, model) in self.models.iter().enumerate() {
+            let mut options = options.clone();
+            if i > 0 {
+                options.context = None;
+            }
+            let response = match model
+                .text_complete_stream(prompt, system_prompt, options)
+                .await
+            {
+                Ok(response) => response.started().await,
+                Err(e) => Err(e),
+            };
+            let response = match response {
+                Ok(response) => response,
+                Err(e) => {
+                    self.check_fall_back(i, e)?;
+                    continue;
+                }
+            };
+            if response.metadata.served_by().is_none() {
+                response
+                    .metadata
+                    .set_served_by(ServingModel::of(model.as_ref()));
+            }
+            return Ok(response);
+        }
+        unreachable!("The last model either succeeds or returns its error")
+    }
+
+    async fn generate_embedding(&self, prompt: &str) -> Result<Vec<f32>, LanguageModelError> {
+        self.primary().generate_embedding(prompt).await
+    }
+
+    async fn chat_complete(
+        &self,
+        messages: &[ChatMessage],
+        options: TextCompleteOptions,
+    ) -> Result<TextCompleteResponse, LanguageModelError> {
+        for (i, model) in self.models.iter().enumerate() {
+            match model.chat_complete(messages, options.clone()).await {
+                Ok(mut response) => {
+                    response
+                        .served_by
+                        .get_or_insert_with(|| ServingModel::of(model.as_ref()));
+                    return Ok(response);
+                }
+                Err(e) => self.check_fall_back(i, e)?,
+            }
+        }
+        unreachable!("The last model either succeeds or returns its error")
+    }
+
+    fn provider(&self) -> LanguageModelProvider {
+        self.primary().provider()
+    }
+
+    fn text_completion_model_name(&self) -> String {
+        self.primary().text_completion_model_name()
+    }
+
+    fn embedding_model_name(&self) -> String {
+        self.primary().embedding_model_name()
+    }
+}
+
+/// Builds a [`FallbackLanguageModel`] instance.
+pub struct FallbackLanguageModelBuilder {
+    models: Vec<Box<dyn LanguageModel>>,
+    policy: FallbackPolicy,
+}
+
+impl FallbackLanguageModelBuilder {
+    /// Adds a model, which is tried after the models added before it.
+    pub fn with_lm(mut self, lm: Box<dyn LanguageModel>) -> Self {
+        self.models.push(lm);
+        self
+    }
+
+    /// Sets which errors cause a fall back to the next model.
+    /// Defaults to [`FallbackPolicy::ApiErrors`].
+    pub fn with_policy(mut self, policy: FallbackPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+}
+
+impl LanguageModelBuilder<FallbackLanguageModel> for FallbackLanguageModelBuilder {
+    fn new() -> Self {
+        Self {
+            models: Vec::new(),
+            policy: FallbackPolicy::default(),
+        }
+    }
+
+    /// Tries to build a [`FallbackLanguageModel`] instance. Fails if no models were added.
+    fn try_build(self) -> Result<FallbackLanguageModel, LanguageModelBuilderError> {
+        if self.models.is_empty() {
+            return Err(LanguageModelBuilderError::ConfigurationNotSet(
+                "Language models".to_string(),
+            ));
+        }
+        Ok(FallbackLanguageModel {
+            models: self.models,
+            policy: self.policy,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio_stream::StreamExt;
+
+    use crate::{
+        lm::OllamaError,
+        test_utils::{FailingLanguageModel, ScriptedLanguageModel},
+    };
+
+    use super::*;
+
+    fn unavailable() -> LanguageModelError {
+        LanguageModelError::Ollama(OllamaError::ApiUnavailable(
+            "Connection refused".to_string(),
+        ))
+    }
+
+    fn configuration() -> LanguageModelError {
+        LanguageModelError::Configuration("Missing API key".to_string())
+    }
+
+    #[tokio::test]
+    async fn test_fallback() {
+        let lm = FallbackLanguageModelBuilder::new()
+            .with_lm(Box::new(FailingLanguageModel::new(unavailable)))
+            .with_lm(Box::new(ScriptedLanguageModel::new(&["Paris", "Lyon"])))
+            .try_build()
+            .unwrap();
+
+        let response = lm
+            .text_complete("Capital of France?", "", TextCompleteOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(response.text, "Paris");
+        assert_eq!(
+            response.served_by.map(|served_by| served_by.model),
+            Some("scripted".to_string())
+        );
+
+        let mut response = lm
+            .text_complete_stream("Capital?", "", TextCompleteStreamOptions::default())
+            .await
+            .unwrap();
+        let mut text = String::new();
+        while let Some(chunk) = response.stream.next().await {
+            text.push_str(&chunk.unwrap());
+        }
+        assert_eq!(text, "Lyon");
+        assert_eq!(
+            response
+                .metadata
+                .served_by()
+                .map(|served_by| served_by.model),
+            Some("scripted".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fallback_policy() {
+        let lm = FallbackLanguageModelBuilder::new()
+            .with_lm(Box::new(FailingLanguageModel::new(configuration)))
+            .with_lm(Box::new(ScriptedLanguageModel::new(&["Paris"])))
+            .with_policy(FallbackPolicy::Unavailable)
+            .try_build()
+            .unwrap();
+
+        let result = lm
+            .text_complete("Capital of France?", "", TextCompleteOptions::default())
+            .await;
+        assert!(matches!(result, Err(LanguageModelError::Configuration(_))));
+    }
+}
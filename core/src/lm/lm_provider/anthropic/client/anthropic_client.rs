@@ -1,14 +1,21 @@
 #![allow(dead_code)]
 
+use async_gen::AsyncIter;
+use reqwest::header::{HeaderMap, HeaderValue};
 use thiserror::Error;
+use tokio_stream::{Stream, StreamExt};
 
-use crate::lm::lm_provider::anthropic::client::models::AnthropicMessagesApiRequest;
+use crate::{
+    lm::{lm_provider::anthropic::client::models::AnthropicMessagesApiRequest, ApiErrorDetails},
+    net::{self, EventStreamFormat, NetError, SseClient},
+};
 
 use super::{
-    config::DEFAULT_MAX_TOKENS,
+    config::{ANTHROPIC_VERSION, DEFAULT_MAX_TOKENS},
     models::{
-        AnthropicMessage, AnthropicMessagesApiMessage, AnthropicMessagesApiResponse,
-        AnthropicMessagesApiResponseSuccess,
+        AnthropicApiError, AnthropicContentBlock, AnthropicMessage, AnthropicMessageContent,
+        AnthropicMessagesApiMessage, AnthropicMessagesApiResponse,
+        AnthropicMessagesApiResponseSuccess, AnthropicStreamEvent, AnthropicTool,
     },
 };
 
@@ -25,6 +32,12 @@ pub(crate) enum AnthropicClientError {
 
     #[error("Failed to send or receive request to/from Anthropic API: {0}")]
     Api(String),
+
+    #[error("Anthropic API is not available: {0}")]
+    Unavailable(String),
+
+    #[error("Anthropic API returned an error: {0}")]
+    Http(Box<ApiErrorDetails>),
 }
 
 /// A client for interacting with the Anthropic API.
@@ -49,35 +62,14 @@ impl AnthropicClient {
         system_prompt: &str,
         options: AnthropicClientTextCompleteOptions,
     ) -> Result<AnthropicMessagesApiResponseSuccess, AnthropicClientError> {
-        let messages_api_endpoint = format!("{}/v1/messages", self.api_endpoint);
-
-        let system_prompt = if system_prompt.is_empty() {
-            None
-        } else {
-            Some(system_prompt.to_string())
-        };
-
-        let messages = messages
-            .iter()
-            .map(Self::construct_message)
-            .collect::<Vec<_>>();
-
-        let req_body = AnthropicMessagesApiRequest {
-            messages,
-            system_prompt,
-            model: options.model,
-            max_tokens_to_sample: DEFAULT_MAX_TOKENS,
-            stop_sequences: None,
-            temperature: None,
-            top_k: None,
-        };
+        let req_body = Self::messages_request(messages, system_prompt, options, false);
 
         let http_client = reqwest::Client::new();
         let req = http_client
-            .post(messages_api_endpoint)
+            .post(format!("{}/v1/messages", self.api_endpoint))
             // See Anthropic authentication documentation: https://docs.anthropic.com/en/api/getting-started#authentication
             .header("x-api-key", &self.api_key)
-            .header("anthropic-version", "2023-06-01")
+            .header("anthropic-version", ANTHROPIC_VERSION)
             .header(reqwest::header::CONTENT_TYPE, "application/json")
             .body(
                 serde_json::to_string(&req_body)
@@ -89,13 +81,22 @@ impl AnthropicClient {
         let response = http_client
             .execute(req)
             .await
-            .map_err(|e| AnthropicClientError::Api(e.to_string()))?;
+            .map_err(|e| AnthropicClientError::Unavailable(e.to_string()))?;
+        let status = response.status();
+        let headers = response.headers().clone();
 
         let response_body_json = response
             .text()
             .await
             .map_err(|e| AnthropicClientError::Api(e.to_string()))?
             .to_string();
+        if !status.is_success() {
+            return Err(Self::http_error(
+                status.as_u16(),
+                &headers,
+                response_body_json,
+            ));
+        }
 
         let deserialized_response: AnthropicMessagesApiResponse =
             serde_json::from_str(&response_body_json).map_err(|e| {
@@ -113,15 +114,183 @@ impl AnthropicClient {
         })
     }
 
+    /// Generates a streaming response from the Anthropic API.
+    ///
+    /// # Arguments
+    /// * `messages` - The messages of the conversation.
+    /// * `system_prompt` - The system prompt to use for the generation.
+    /// * `options` - The options for the generation (use [`AnthropicClientTextCompleteOptionsBuilder`] to build a new instance).
+    ///
+    /// # Returns
+    /// A [Result] containing a stream of events from the Anthropic API or an error if there was a problem.
+    pub fn text_complete_stream(
+        &self,
+        messages: &[AnthropicMessage],
+        system_prompt: &str,
+        options: AnthropicClientTextCompleteOptions,
+    ) -> Result<
+        impl Stream<Item = Result<AnthropicStreamEvent, AnthropicClientError>>,
+        AnthropicClientError,
+    > {
+        let req_body = Self::messages_request(messages, system_prompt, options, true);
+        let body = serde_json::to_string(&req_body)
+            .map_err(|e| AnthropicClientError::Marhsalling(e.to_string()))?;
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "x-api-key",
+            self.api_key
+                .parse()
+                .map_err(|_| AnthropicClientError::ConfigurationNotSet("API key".to_string()))?,
+        );
+        headers.insert(
+            "anthropic-version",
+            HeaderValue::from_static(ANTHROPIC_VERSION),
+        );
+
+        let url = format!("{}/v1/messages", self.api_endpoint);
+        let mut events = Box::pin(SseClient::post(
+            &url,
+            EventStreamFormat::EventStream,
+            headers,
+            Some(body),
+        ));
+        Ok(AsyncIter::from(async_gen::gen! {
+            while let Some(event) = events.next().await {
+                let event = match event {
+                    Ok(event) => event,
+                    Err(NetError::Request(e)) => {
+                        yield Err(AnthropicClientError::Unavailable(e));
+                        return;
+                    }
+                    Err(NetError::Status { status, headers, body }) => {
+                        yield Err(Self::http_error(status, &headers, body));
+                        return;
+                    }
+                    Err(e) => {
+                        yield Err(AnthropicClientError::Api(e.to_string()));
+                        return;
+                    }
+                };
+                // The event type is also part of the data, so the `event` field is redundant.
+                match serde_json::from_str::<AnthropicStreamEvent>(&event.data) {
+                    Ok(AnthropicStreamEvent::MessageStop) => {
+                        yield Ok(AnthropicStreamEvent::MessageStop);
+                        return;
+                    }
+                    Ok(event) => {
+                        yield Ok(event);
+                    }
+                    Err(e) => {
+                        yield Err(AnthropicClientError::Marhsalling(format!(
+                            "Failed to parse event: {e} (event: {})",
+                            event.data
+                        )));
+                        return;
+                    }
+                }
+            }
+        }))
+    }
+
+    /// Returns the error of a response with an unsuccessful HTTP status.
+    fn http_error(status: u16, headers: &HeaderMap, body: String) -> AnthropicClientError {
+        let error = serde_json::from_str::<AnthropicApiError>(&body)
+            .ok()
+            .map(|error_response| error_response.error);
+        AnthropicClientError::Http(Box::new(ApiErrorDetails {
+            status,
+            message: error
+                .as_ref()
+                .map_or_else(|| body.clone(), |error| error.message.clone()),
+            error_type: error.map(|error| error.typ),
+            code: None,
+            request_id: net::header_value(headers, "request-id"),
+            retry_after: net::retry_after(headers),
+            body,
+        }))
+    }
+
+    fn messages_request(
+        messages: &[AnthropicMessage],
+        system_prompt: &str,
+        options: AnthropicClientTextCompleteOptions,
+        stream: bool,
+    ) -> AnthropicMessagesApiRequest {
+        let system_prompt = if system_prompt.is_empty() {
+            None
+        } else {
+            Some(system_prompt.to_string())
+        };
+
+        let mut api_messages: Vec<AnthropicMessagesApiMessage> = Vec::new();
+        for message in messages {
+            let api_message = Self::construct_message(message);
+            // Roles must alternate, so consecutive tool results are merged into a single user message.
+            if let (
+                Some(AnthropicMessagesApiMessage {
+                    content: AnthropicMessageContent::Blocks(previous_blocks),
+                    ..
+                }),
+                AnthropicMessage::ToolResult { .. },
+                AnthropicMessageContent::Blocks(blocks),
+            ) = (api_messages.last_mut(), message, &api_message.content)
+            {
+                if matches!(
+                    previous_blocks.last(),
+                    Some(AnthropicContentBlock::ToolResult { .. })
+                ) {
+                    previous_blocks.extend(blocks.iter().cloned());
+                    continue;
+                }
+            }
+            api_messages.push(api_message);
+        }
+
+        AnthropicMessagesApiRequest {
+            messages: api_messages,
+            system_prompt,
+            model: options.model,
+            max_tokens_to_sample: options.max_tokens_to_sample,
+            stop_sequences: options.stop_sequences,
+            temperature: options.temperature,
+            top_p: options.top_p,
+            top_k: options.top_k,
+            stream: if stream { Some(true) } else { None },
+            tools: options.tools,
+        }
+    }
+
     fn construct_message(msg: &AnthropicMessage) -> AnthropicMessagesApiMessage {
         match msg {
             AnthropicMessage::User(content) => AnthropicMessagesApiMessage {
                 role: "user".to_string(),
-                content: content.to_string(),
+                content: AnthropicMessageContent::Text(content.to_string()),
             },
             AnthropicMessage::Assistant(content) => AnthropicMessagesApiMessage {
                 role: "assistant".to_string(),
-                content: content.to_string(),
+                content: AnthropicMessageContent::Text(content.to_string()),
+            },
+            AnthropicMessage::AssistantToolUse { text, tool_uses } => {
+                let mut blocks = Vec::new();
+                if !text.is_empty() {
+                    blocks.push(AnthropicContentBlock::Text { text: text.clone() });
+                }
+                blocks.extend(tool_uses.iter().cloned());
+                AnthropicMessagesApiMessage {
+                    role: "assistant".to_string(),
+                    content: AnthropicMessageContent::Blocks(blocks),
+                }
+            }
+            AnthropicMessage::ToolResult {
+                tool_use_id,
+                content,
+            } => AnthropicMessagesApiMessage {
+                role: "user".to_string(),
+                content: AnthropicMessageContent::Blocks(vec![AnthropicContentBlock::ToolResult {
+                    tool_use_id: tool_use_id.to_string(),
+                    content: content.to_string(),
+                }]),
             },
         }
     }
@@ -138,8 +307,12 @@ pub struct AnthropicClientTextCompleteOptions {
     pub stop_sequences: Option<Vec<String>>,
     /// See [`AnthropicCompleteApiRequest::temperature`].
     pub temperature: Option<f32>,
+    /// See [`AnthropicCompleteApiRequest::top_p`].
+    pub top_p: Option<f32>,
     /// See [`AnthropicCompleteApiRequest::top_k`].
     pub top_k: Option<usize>,
+    /// See [`AnthropicCompleteApiRequest::tools`].
+    pub tools: Option<Vec<AnthropicTool>>,
 }
 
 /// Builds a new [`AnthropicClientTextCompleteOptions`] instance.
@@ -149,7 +322,9 @@ pub struct AnthropicClientTextCompleteOptionsBuilder {
     max_tokens: usize,
     stop_sequences: Option<Vec<String>>,
     temperature: Option<f32>,
+    top_p: Option<f32>,
     top_k: Option<usize>,
+    tools: Option<Vec<AnthropicTool>>,
 }
 
 impl AnthropicClientTextCompleteOptionsBuilder {
@@ -185,12 +360,24 @@ impl AnthropicClientTextCompleteOptionsBuilder {
         self
     }
 
+    /// Sets the top p (nucleus sampling).
+    pub fn with_top_p(mut self, top_p: f32) -> Self {
+        self.top_p = Some(top_p);
+        self
+    }
+
     /// Sets the top k.
     pub fn with_top_k(mut self, top_k: usize) -> Self {
         self.top_k = Some(top_k);
         self
     }
 
+    /// Sets the tools which the model may use.
+    pub fn with_tools(mut self, tools: Vec<AnthropicTool>) -> Self {
+        self.tools = Some(tools);
+        self
+    }
+
     /// Tries to build a [`AnthropicClientTextCompleteOptions`] instance. May fail if the required configurations are not set.
     pub fn try_build(self) -> Result<AnthropicClientTextCompleteOptions, AnthropicClientError> {
         let Some(model) = self.model else {
@@ -203,7 +390,9 @@ impl AnthropicClientTextCompleteOptionsBuilder {
             max_tokens_to_sample: self.max_tokens,
             stop_sequences: self.stop_sequences,
             temperature: self.temperature,
+            top_p: self.top_p,
             top_k: self.top_k,
+            tools: self.tools,
         })
     }
 }
@@ -6,3 +6,6 @@ pub const DEFAULT_API_ENDPOINT: &str = "https://api.anthropic.com";
 pub const DEFAULT_MODEL: &str = anthropic_model::CLAUDE_3_5_SONNET;
 /// Default maximum number of tokens to generate before stopping.
 pub const DEFAULT_MAX_TOKENS: usize = 2048;
+/// Version of the Anthropic API, sent in the `anthropic-version` header.
+/// See [versioning](https://docs.anthropic.com/en/api/versioning).
+pub const ANTHROPIC_VERSION: &str = "2023-06-01";
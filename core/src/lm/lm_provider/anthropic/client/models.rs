@@ -40,6 +40,12 @@ pub struct AnthropicMessagesApiRequest {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub temperature: Option<f32>,
 
+    /// Use nucleus sampling: cut off the cumulative distribution over subsequent tokens at the probability specified by `top_p`.
+    ///
+    /// You should either alter temperature or top_p, but not both.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+
     /// Only sample from the top K options for each subsequent token.
     ///
     /// Used to remove "long tail" low probability responses. Learn more technical details [here](https://towardsdatascience.com/how-to-sample-from-language-models-682bceb97277).
@@ -47,14 +53,46 @@ pub struct AnthropicMessagesApiRequest {
     /// Recommended for advanced use cases only. You usually only need to use temperature.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub top_k: Option<usize>,
+
+    /// Whether to incrementally stream the response using server-sent events.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream: Option<bool>,
+
+    /// Definitions of tools that the model may use.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<AnthropicTool>>,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+/// A tool which the model may use.
+/// Referenced from the Anthropic API documentation [here](https://docs.anthropic.com/en/docs/build-with-claude/tool-use).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnthropicTool {
+    /// Name of the tool.
+    pub name: String,
+
+    /// Description of the tool.
+    pub description: String,
+
+    /// JSON schema for the input of the tool.
+    pub input_schema: serde_json::Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum AnthropicMessage {
     /// A user message.
     User(String),
     /// An assistant message.
     Assistant(String),
+    /// An assistant message which requested the use of tools.
+    AssistantToolUse {
+        text: String,
+        tool_uses: Vec<AnthropicContentBlock>,
+    },
+    /// The result of a tool use (sent as a user message).
+    ToolResult {
+        tool_use_id: String,
+        content: String,
+    },
 }
 
 #[derive(Debug)]
@@ -79,7 +117,40 @@ pub struct AnthropicMessagesApiMessage {
     pub role: String,
 
     /// The content of the message.
-    pub content: String,
+    pub content: AnthropicMessageContent,
+}
+
+/// Content of a message, which is either a string or a list of content blocks.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum AnthropicMessageContent {
+    Text(String),
+    Blocks(Vec<AnthropicContentBlock>),
+}
+
+/// A content block of a message.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AnthropicContentBlock {
+    /// Text content.
+    Text { text: String },
+
+    /// A request of the model to use a tool.
+    ToolUse {
+        id: String,
+        name: String,
+        input: serde_json::Value,
+    },
+
+    /// The result of a tool use.
+    ToolResult {
+        tool_use_id: String,
+        content: String,
+    },
+
+    /// Content block types which may be added in the future.
+    #[serde(other)]
+    Unknown,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -103,26 +174,20 @@ pub struct AnthropicMessagesApiResponseSuccess {
     /// The model that generated the response.
     pub model: String,
 
-    /// The resulting completion up to and excluding the stop sequences.
-    pub content: Vec<AnthropicMessagesApiResponseSuccessContent>,
+    /// The resulting completion up to and excluding the stop sequences, as a list of content blocks
+    /// (text, or tool uses when tools were provided).
+    pub content: Vec<AnthropicContentBlock>,
 
     /// The reason that the model stopped generating tokens.
     ///
     /// This may be one the following values:
     /// - "stop_sequence": Reached a stop sequence — either provided by you via the stop_sequences parameter, or a stop sequence built into the model
     /// - "max_tokens": Exceeded `max_tokens_to_sample` or the model's maximum
+    /// - "tool_use": The model requested the use of one or more tools
     pub stop_reason: Option<String>,
-}
 
-/// Response from the Anthropic API for the messages API endpoint.
-#[derive(Debug, Serialize, Deserialize)]
-pub struct AnthropicMessagesApiResponseSuccessContent {
-    /// Type of the response, for text completion, this will be "text".
-    #[serde(rename = "type")]
-    pub typ: String,
-
-    /// The content of the response.
-    pub text: String,
+    /// Token usage of the message.
+    pub usage: Option<AnthropicUsage>,
 }
 
 /// Response from the Anthropic API which indicates an error.
@@ -145,3 +210,80 @@ pub struct AnthropicApiErrorBody {
     /// Error message.
     pub message: String,
 }
+
+/// Event received when streaming a response from the Anthropic Messages API.
+/// Referenced from the Anthropic API documentation [here](https://docs.anthropic.com/en/api/messages-streaming).
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AnthropicStreamEvent {
+    /// Sent first, contains a [`AnthropicStreamMessage`] with empty content.
+    MessageStart { message: AnthropicStreamMessage },
+
+    /// Start of a content block, which will be filled by subsequent deltas.
+    ContentBlockStart { index: usize },
+
+    /// Incremental update to a content block.
+    ContentBlockDelta {
+        index: usize,
+        delta: AnthropicStreamContentDelta,
+    },
+
+    /// End of a content block.
+    ContentBlockStop { index: usize },
+
+    /// Top-level changes to the message (e.g., the stop reason and cumulative usage).
+    MessageDelta {
+        delta: AnthropicStreamMessageDelta,
+        usage: AnthropicUsage,
+    },
+
+    /// Final event of the stream.
+    MessageStop,
+
+    /// Sent periodically to keep the connection alive.
+    Ping,
+
+    /// An error which occured during the stream (e.g., "overloaded_error").
+    Error { error: AnthropicApiErrorBody },
+
+    /// Event types which may be added in the future.
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AnthropicStreamMessage {
+    /// The model that generates the response.
+    pub model: String,
+
+    /// Token usage of the message at the start of the stream.
+    pub usage: AnthropicUsage,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AnthropicStreamContentDelta {
+    /// A text delta to append to a text content block.
+    TextDelta { text: String },
+
+    /// Delta types which may be added in the future.
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AnthropicStreamMessageDelta {
+    /// The reason that the model stopped generating tokens (e.g., "end_turn" or "max_tokens").
+    pub stop_reason: Option<String>,
+}
+
+/// Token usage of a message.
+/// Note that when streaming, the counts in `message_delta` events are cumulative.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AnthropicUsage {
+    /// Number of input tokens.
+    pub input_tokens: Option<usize>,
+
+    /// Number of output tokens.
+    pub output_tokens: Option<usize>,
+}
@@ -1,15 +1,25 @@
+use async_gen::AsyncIter;
 use async_trait::async_trait;
 use thiserror::Error;
+use tokio_stream::StreamExt;
 
 use crate::lm::{
-    LanguageModel, LanguageModelError, LanguageModelProvider, TextCompleteOptions,
-    TextCompleteResponse, TextCompleteStreamOptions, TextCompleteStreamResponse,
+    ApiErrorDetails, ChatMessage, ChatRole, FinishReason, GenerationOptions, LanguageModel,
+    LanguageModelError, LanguageModelProvider, ResponseFormat, ServingModel, TextCompleteOptions,
+    TextCompleteResponse, TextCompleteStreamMetadata, TextCompleteStreamOptions,
+    TextCompleteStreamResponse, TokenUsage, Tool, ToolCall,
 };
 
 use super::client::{
-    anthropic_client::AnthropicClientTextCompleteOptionsBuilder,
+    anthropic_client::{
+        AnthropicClient, AnthropicClientError, AnthropicClientTextCompleteOptions,
+        AnthropicClientTextCompleteOptionsBuilder,
+    },
     builder::AnthropicClientBuilder,
-    models::{AnthropicMessage, AnthropicMessageRole},
+    models::{
+        AnthropicApiErrorBody, AnthropicContentBlock, AnthropicMessage, AnthropicMessageRole,
+        AnthropicStreamContentDelta, AnthropicStreamEvent, AnthropicTool,
+    },
 };
 
 #[derive(Debug, Clone)]
@@ -24,21 +34,32 @@ pub enum AnthropicError {
     #[error("Unexpected response from API. Error: {0}")]
     Api(String),
 
+    #[error("Anthropic API returned an error: {0}")]
+    Http(Box<ApiErrorDetails>),
+
     #[error("Configuration error: {0}")]
     Configuration(String),
 
     #[error("Serialization error: {0}")]
     Serialization(String),
 
-    #[error(
-        "OpenAi API is not available. Please check if OpenAi is running in the specified port. Error: {0}"
-    )]
+    #[error("Anthropic API is not available. Error: {0}")]
     ApiUnavailable(String),
 
     #[error("Invalid input: {0}")]
     InvalidInput(String),
 }
 
+impl From<AnthropicClientError> for LanguageModelError {
+    fn from(error: AnthropicClientError) -> Self {
+        LanguageModelError::Anthropic(match error {
+            AnthropicClientError::Unavailable(e) => AnthropicError::ApiUnavailable(e),
+            AnthropicClientError::Http(details) => AnthropicError::Http(details),
+            e => AnthropicError::Api(e.to_string()),
+        })
+    }
+}
+
 #[async_trait]
 impl LanguageModel for Anthropic {
     // TODO: Support context.
@@ -46,51 +67,140 @@ impl LanguageModel for Anthropic {
         &self,
         prompt: &str,
         system_prompt: &str,
-        _options: TextCompleteOptions,
+        options: TextCompleteOptions,
     ) -> Result<TextCompleteResponse, LanguageModelError> {
-        let client = AnthropicClientBuilder::new()
-            .with_api_endpoint(self.api_endpoint.clone())
-            .with_api_key(self.api_key.clone())
-            .try_build()
-            .map_err(|e| {
-                LanguageModelError::Anthropic(AnthropicError::Configuration(e.to_string()))
-            })?;
-
-        let options = AnthropicClientTextCompleteOptionsBuilder::new()
-            .with_model(self.model.clone())
-            .try_build()
-            .map_err(|e| {
-                LanguageModelError::Anthropic(AnthropicError::Configuration(e.to_string()))
-            })?;
-
         // In the case of Anthropic, we need to supply the full history of the conversation.
         // We therefore parse the prompt string and construct the messages.
         let messages = Self::messages_from_prompt(prompt)?;
 
-        let response = client
-            .text_complete(messages.as_slice(), system_prompt, options)
+        self.messages_complete(&messages, system_prompt, options)
             .await
-            .map_err(|e| LanguageModelError::Anthropic(AnthropicError::Api(e.to_string())))?;
-
-        let response_content = response
-            .content
-            .first()
-            .ok_or(AnthropicError::Api("Response content is empty".to_string()))?;
-        Ok(TextCompleteResponse {
-            text: response_content.text.clone(),
-            context: None,
-        })
     }
 
     async fn text_complete_stream(
         &self,
-        _prompt: &str,
-        _system_prompt: &str,
-        _options: TextCompleteStreamOptions,
+        prompt: &str,
+        system_prompt: &str,
+        options: TextCompleteStreamOptions,
     ) -> Result<TextCompleteStreamResponse, LanguageModelError> {
-        return Err(LanguageModelError::UnsupportedFeature(
-            "Streaming is not supported for Anthropic".to_string(),
-        ));
+        let client = self.client()?;
+        let client_options = self.client_options(&options.generation, &[])?;
+
+        let mut messages = Self::messages_from_prompt(prompt)?;
+        let prefill = Self::prefill(&options.response_format, &messages);
+        if !prefill.is_empty() {
+            messages.push(AnthropicMessage::Assistant(prefill.to_string()));
+        }
+        let mut events = Box::pin(
+            client
+                .text_complete_stream(messages.as_slice(), system_prompt, client_options)
+                .map_err(LanguageModelError::from)?,
+        );
+
+        let metadata = TextCompleteStreamMetadata::default();
+        metadata.set_served_by(ServingModel::of(self));
+        let stream_metadata = metadata.clone();
+        let stream = AsyncIter::from(async_gen::gen! {
+            // The prefill is part of the response.
+            if !prefill.is_empty() {
+                yield Ok(prefill.to_string());
+            }
+            let mut usage = TokenUsage::default();
+            while let Some(event) = events.next().await {
+                match event {
+                    Ok(AnthropicStreamEvent::MessageStart { message }) => {
+                        usage.prompt_tokens = message.usage.input_tokens.unwrap_or_default();
+                        usage.completion_tokens = message.usage.output_tokens.unwrap_or_default();
+                    }
+                    Ok(AnthropicStreamEvent::ContentBlockDelta {
+                        delta: AnthropicStreamContentDelta::TextDelta { text },
+                        ..
+                    }) => {
+                        yield Ok(text);
+                    }
+                    Ok(AnthropicStreamEvent::MessageDelta { delta, usage: delta_usage }) => {
+                        if let Some(stop_reason) = &delta.stop_reason {
+                            stream_metadata.set_finish_reason(Self::finish_reason(stop_reason));
+                        }
+                        // Output token counts in `message_delta` events are cumulative.
+                        if let Some(output_tokens) = delta_usage.output_tokens {
+                            usage.completion_tokens = output_tokens;
+                        }
+                    }
+                    Ok(AnthropicStreamEvent::MessageStop) => {
+                        stream_metadata.set_usage(usage);
+                        return;
+                    }
+                    Ok(AnthropicStreamEvent::Error { error }) => {
+                        yield Err(LanguageModelError::Anthropic(Self::stream_error(error)));
+                        return;
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        yield Err(LanguageModelError::from(e));
+                        return;
+                    }
+                }
+            }
+        });
+        Ok(TextCompleteStreamResponse {
+            stream: Box::pin(stream),
+            metadata,
+        })
+    }
+
+    async fn chat_complete(
+        &self,
+        messages: &[ChatMessage],
+        options: TextCompleteOptions,
+    ) -> Result<TextCompleteResponse, LanguageModelError> {
+        // The Anthropic Messages API receives the system prompt separately from the messages.
+        let system_prompt = messages
+            .iter()
+            .filter(|message| message.role == ChatRole::System)
+            .map(|message| message.content.as_str())
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        let messages = messages
+            .iter()
+            .map(|message| match message.role {
+                ChatRole::System => Ok(None),
+                ChatRole::User => Ok(Some(AnthropicMessage::User(message.content.to_owned()))),
+                ChatRole::Assistant if !message.tool_calls.is_empty() => {
+                    Ok(Some(AnthropicMessage::AssistantToolUse {
+                        text: message.content.to_owned(),
+                        tool_uses: message
+                            .tool_calls
+                            .iter()
+                            .map(|tool_call| AnthropicContentBlock::ToolUse {
+                                id: tool_call.id.to_owned(),
+                                name: tool_call.name.to_owned(),
+                                input: tool_call.arguments.clone(),
+                            })
+                            .collect(),
+                    }))
+                }
+                ChatRole::Assistant => Ok(Some(AnthropicMessage::Assistant(
+                    message.content.to_owned(),
+                ))),
+                ChatRole::Tool => {
+                    let tool_use_id = message.tool_call_id.clone().ok_or_else(|| {
+                        AnthropicError::InvalidInput(
+                            "Tool messages require a tool_call_id".to_string(),
+                        )
+                    })?;
+                    Ok(Some(AnthropicMessage::ToolResult {
+                        tool_use_id,
+                        content: message.content.to_owned(),
+                    }))
+                }
+            })
+            .filter_map(Result::transpose)
+            .collect::<Result<Vec<_>, AnthropicError>>()
+            .map_err(LanguageModelError::Anthropic)?;
+
+        self.messages_complete(&messages, &system_prompt, options)
+            .await
     }
 
     async fn generate_embedding(&self, _prompt: &str) -> Result<Vec<f32>, LanguageModelError> {
@@ -113,6 +223,161 @@ impl LanguageModel for Anthropic {
 }
 
 impl Anthropic {
+    fn client(&self) -> Result<AnthropicClient, LanguageModelError> {
+        AnthropicClientBuilder::new()
+            .with_api_endpoint(self.api_endpoint.clone())
+            .with_api_key(self.api_key.clone())
+            .try_build()
+            .map_err(|e| {
+                LanguageModelError::Anthropic(AnthropicError::Configuration(e.to_string()))
+            })
+    }
+
+    fn client_options(
+        &self,
+        generation: &GenerationOptions,
+        tools: &[Tool],
+    ) -> Result<AnthropicClientTextCompleteOptions, LanguageModelError> {
+        if generation.seed.is_some() {
+            return Err(LanguageModelError::UnsupportedFeature(
+                "The 'seed' generation option is not supported by Anthropic".to_string(),
+            ));
+        }
+        if generation.temperature.is_some() && generation.top_p.is_some() {
+            // See https://docs.anthropic.com/en/api/messages
+            return Err(LanguageModelError::UnsupportedFeature(
+                "Anthropic supports only one of the 'temperature' and 'top_p' generation options"
+                    .to_string(),
+            ));
+        }
+        generation.validate_ranges("Anthropic", Some(1.0))?;
+
+        let mut builder =
+            AnthropicClientTextCompleteOptionsBuilder::new().with_model(self.model.clone());
+        if let Some(max_tokens) = generation.max_tokens {
+            builder = builder.with_max_tokens(max_tokens);
+        }
+        if let Some(stop) = &generation.stop {
+            builder = builder.with_stop_sequences(stop.clone());
+        }
+        if let Some(temperature) = generation.temperature {
+            builder = builder.with_temperature(temperature);
+        }
+        if let Some(top_p) = generation.top_p {
+            builder = builder.with_top_p(top_p);
+        }
+        if !tools.is_empty() {
+            builder = builder.with_tools(
+                tools
+                    .iter()
+                    .map(|tool| AnthropicTool {
+                        name: tool.name.to_owned(),
+                        description: tool.description.to_owned(),
+                        input_schema: tool.parameters.clone(),
+                    })
+                    .collect(),
+            );
+        }
+        builder.try_build().map_err(|e| {
+            LanguageModelError::Anthropic(AnthropicError::Configuration(e.to_string()))
+        })
+    }
+
+    /// Returns the error of an error event (e.g., "overloaded_error") received while streaming.
+    ///
+    /// The response itself was successful, so the status is the one documented for the error type
+    /// (see the Anthropic API documentation [here](https://docs.anthropic.com/en/api/errors#http-errors)).
+    fn stream_error(error: AnthropicApiErrorBody) -> AnthropicError {
+        let status = match error.typ.as_str() {
+            "invalid_request_error" => 400,
+            "authentication_error" => 401,
+            "permission_error" => 403,
+            "not_found_error" => 404,
+            "request_too_large" => 413,
+            "rate_limit_error" => 429,
+            "overloaded_error" => 529,
+            _ => 500,
+        };
+        AnthropicError::Http(Box::new(ApiErrorDetails {
+            status,
+            body: serde_json::to_string(&error).unwrap_or_default(),
+            message: error.message,
+            error_type: Some(error.typ),
+            code: None,
+            request_id: None,
+            retry_after: None,
+        }))
+    }
+
+    fn finish_reason(stop_reason: &str) -> FinishReason {
+        match stop_reason {
+            "end_turn" | "stop_sequence" => FinishReason::Stop,
+            "max_tokens" => FinishReason::Length,
+            "tool_use" => FinishReason::ToolCalls,
+            other => FinishReason::Other(other.to_owned()),
+        }
+    }
+
+    async fn messages_complete(
+        &self,
+        messages: &[AnthropicMessage],
+        system_prompt: &str,
+        options: TextCompleteOptions,
+    ) -> Result<TextCompleteResponse, LanguageModelError> {
+        let client = self.client()?;
+        let client_options = self.client_options(&options.generation, &options.tools)?;
+
+        let prefill = Self::prefill(&options.response_format, messages);
+        let mut messages = messages.to_vec();
+        if !prefill.is_empty() {
+            messages.push(AnthropicMessage::Assistant(prefill.to_string()));
+        }
+
+        let response = client
+            .text_complete(&messages, system_prompt, client_options)
+            .await
+            .map_err(LanguageModelError::from)?;
+
+        let mut text = prefill.to_string();
+        let mut tool_calls = Vec::new();
+        for block in response.content {
+            match block {
+                AnthropicContentBlock::Text { text: block_text } => text.push_str(&block_text),
+                AnthropicContentBlock::ToolUse { id, name, input } => tool_calls.push(ToolCall {
+                    id,
+                    name,
+                    arguments: input,
+                }),
+                _ => {}
+            }
+        }
+        if text.len() == prefill.len() && tool_calls.is_empty() {
+            return Err(AnthropicError::Api("Response content is empty".to_string()).into());
+        }
+        Ok(TextCompleteResponse {
+            text,
+            context: None,
+            usage: response.usage.map(|usage| TokenUsage {
+                prompt_tokens: usage.input_tokens.unwrap_or_default(),
+                completion_tokens: usage.output_tokens.unwrap_or_default(),
+            }),
+            finish_reason: response.stop_reason.as_deref().map(Self::finish_reason),
+            tool_calls,
+            served_by: Some(ServingModel::of(self)),
+        })
+    }
+
+    /// Returns the text with which the response is prefilled.
+    ///
+    /// Anthropic has no JSON mode, so the response is prefilled with the start of a JSON object.
+    /// A conversation which already ends with an assistant message is continued as is.
+    fn prefill(response_format: &ResponseFormat, messages: &[AnthropicMessage]) -> &'static str {
+        match (response_format, messages.last()) {
+            (ResponseFormat::Text, _) | (_, Some(AnthropicMessage::Assistant(_))) => "",
+            (ResponseFormat::Json | ResponseFormat::JsonSchema(_), _) => "{",
+        }
+    }
+
     fn messages_from_prompt(prompt: &str) -> Result<Vec<AnthropicMessage>, LanguageModelError> {
         if !prompt.starts_with("User:") && !prompt.starts_with("Assistant:") {
             // Assume the prompt is just the user message.
@@ -175,6 +440,8 @@ impl Anthropic {
 
 #[cfg(test)]
 mod tests {
+    use crate::lm::{AnthropicBuilder, LanguageModelBuilder};
+
     use super::*;
 
     #[test]
@@ -203,4 +470,109 @@ mod tests {
             AnthropicMessage::User("How are you?".to_string())
         );
     }
+
+    #[test]
+    fn test_stream_events_deserialization() {
+        let event: AnthropicStreamEvent = serde_json::from_str(
+            r#"{"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Hello"}}"#,
+        )
+        .unwrap();
+        let AnthropicStreamEvent::ContentBlockDelta {
+            delta: AnthropicStreamContentDelta::TextDelta { text },
+            ..
+        } = event
+        else {
+            panic!("Expected a text delta");
+        };
+        assert_eq!(text, "Hello");
+
+        let event: AnthropicStreamEvent = serde_json::from_str(
+            r#"{"type": "message_delta", "delta": {"stop_reason": "end_turn", "stop_sequence": null}, "usage": {"output_tokens": 15}}"#,
+        )
+        .unwrap();
+        let AnthropicStreamEvent::MessageDelta { delta, usage } = event else {
+            panic!("Expected a message delta");
+        };
+        assert_eq!(delta.stop_reason.as_deref(), Some("end_turn"));
+        assert_eq!(usage.output_tokens, Some(15));
+
+        let event: AnthropicStreamEvent =
+            serde_json::from_str(r#"{"type": "some_future_event"}"#).unwrap();
+        assert!(matches!(event, AnthropicStreamEvent::Unknown));
+    }
+
+    #[test]
+    fn test_tool_use_content_deserialization() {
+        let blocks: Vec<AnthropicContentBlock> = serde_json::from_str(
+            r#"[{"type": "text", "text": "Let me check."}, {"type": "tool_use", "id": "toolu_01", "name": "get_weather", "input": {"city": "Paris"}}]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            blocks,
+            vec![
+                AnthropicContentBlock::Text {
+                    text: "Let me check.".to_string()
+                },
+                AnthropicContentBlock::ToolUse {
+                    id: "toolu_01".to_string(),
+                    name: "get_weather".to_string(),
+                    input: serde_json::json!({ "city": "Paris" }),
+                },
+            ]
+        );
+        assert_eq!(
+            Anthropic::finish_reason("tool_use"),
+            FinishReason::ToolCalls
+        );
+    }
+
+    #[tokio::test]
+    async fn test_chat_complete_tool_result_without_id() {
+        let lm = AnthropicBuilder::new()
+            .with_api_key("test".to_string())
+            .try_build()
+            .unwrap();
+        let mut message = ChatMessage::tool_result("toolu_01", "Sunny");
+        message.tool_call_id = None;
+
+        let result = lm
+            .chat_complete(&[message], TextCompleteOptions::default())
+            .await;
+        assert!(matches!(
+            result,
+            Err(LanguageModelError::Anthropic(AnthropicError::InvalidInput(
+                _
+            )))
+        ));
+    }
+
+    #[test]
+    fn test_client_options_generation() {
+        let lm = AnthropicBuilder::new()
+            .with_api_key("test".to_string())
+            .try_build()
+            .unwrap();
+        let generation = |temperature, top_p, seed| GenerationOptions {
+            temperature,
+            top_p,
+            seed,
+            ..Default::default()
+        };
+
+        assert!(lm
+            .client_options(&generation(Some(0.5), None, None), &[])
+            .is_ok());
+        assert!(matches!(
+            lm.client_options(&generation(None, None, Some(42)), &[]),
+            Err(LanguageModelError::UnsupportedFeature(_))
+        ));
+        assert!(matches!(
+            lm.client_options(&generation(Some(0.5), Some(0.9), None), &[]),
+            Err(LanguageModelError::UnsupportedFeature(_))
+        ));
+        assert!(matches!(
+            lm.client_options(&generation(Some(1.5), None, None), &[]),
+            Err(LanguageModelError::Configuration(_))
+        ));
+    }
 }
@@ -4,8 +4,9 @@ use crate::lm::{LanguageModel, LanguageModelProviderError};
 
 use super::{Anthropic, Ollama, OpenAi};
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Default)]
 pub enum LanguageModelProvider {
+    #[default]
     #[serde(rename = "ollama")]
     Ollama,
     #[serde(rename = "openai")]
@@ -1,21 +1,25 @@
 use async_trait::async_trait;
 use lm::{
-    error::LanguageModelError,
+    error::{ApiErrorDetails, LanguageModelError},
     models::{
-        TextCompleteOptions, TextCompleteResponse, TextCompleteStreamOptions,
-        TextCompleteStreamResponse,
+        ChatMessage, FinishReason, GenerationOptions, ResponseFormat, ServingModel,
+        TextCompleteOptions, TextCompleteResponse, TextCompleteStreamMetadata,
+        TextCompleteStreamOptions, TextCompleteStreamResponse, TokenUsage,
     },
-    LanguageModel, LanguageModelProvider,
+    LanguageModel, LanguageModelProvider, ToolCall,
 };
-use net::SseClient;
+use net::{EventStreamFormat, NetError, SseClient};
+use reqwest::header::HeaderMap;
 use thiserror::Error;
 use tokio_stream::StreamExt;
 
 use crate::*;
 
 use super::{
-    OllamaApiModelsMetadata, OllamaEmbeddingsRequest, OllamaEmbeddingsResponse,
-    OllamaGenerateRequest, OllamaGenerateResponse, OllamaGenerateStreamItemResponse,
+    OllamaApiModelsMetadata, OllamaChatMessage, OllamaChatRequest, OllamaChatResponse,
+    OllamaEmbeddingsRequest, OllamaEmbeddingsResponse, OllamaGenerateRequest,
+    OllamaGenerateResponse, OllamaGenerateStreamItemResponse, OllamaModelOptions, OllamaTool,
+    OllamaToolCall, OllamaToolCallFunction, OllamaToolFunction,
 };
 
 #[derive(Debug, Clone)]
@@ -30,6 +34,9 @@ pub enum OllamaError {
     #[error("Unexpected response from API. Error: {0}")]
     Api(String),
 
+    #[error("Ollama API returned an error: {0}")]
+    Http(Box<ApiErrorDetails>),
+
     #[error("Unexpected error when parsing response from Ollama. Error: {0}")]
     Parsing(String),
 
@@ -71,6 +78,63 @@ impl Ollama {
         Ok(parsed_response)
     }
 
+    fn model_options(
+        generation: &GenerationOptions,
+    ) -> Result<OllamaModelOptions, LanguageModelError> {
+        generation.validate_ranges("Ollama", None)?;
+        Ok(OllamaModelOptions {
+            temperature: generation.temperature,
+            num_predict: generation.max_tokens,
+            top_p: generation.top_p,
+            stop: generation.stop.clone(),
+            seed: generation.seed,
+        })
+    }
+
+    fn format(response_format: &ResponseFormat) -> Option<serde_json::Value> {
+        match response_format {
+            ResponseFormat::Text => None,
+            ResponseFormat::Json => Some(serde_json::Value::String("json".to_string())),
+            ResponseFormat::JsonSchema(schema) => Some(schema.clone()),
+        }
+    }
+
+    fn usage(prompt_eval_count: Option<usize>, eval_count: Option<usize>) -> Option<TokenUsage> {
+        if prompt_eval_count.is_none() && eval_count.is_none() {
+            return None;
+        }
+        Some(TokenUsage {
+            prompt_tokens: prompt_eval_count.unwrap_or_default(),
+            completion_tokens: eval_count.unwrap_or_default(),
+        })
+    }
+
+    fn finish_reason(done_reason: &str) -> FinishReason {
+        match done_reason {
+            "stop" => FinishReason::Stop,
+            "length" => FinishReason::Length,
+            other => FinishReason::Other(other.to_owned()),
+        }
+    }
+
+    /// Returns the error of a response with an unsuccessful HTTP status.
+    fn http_error(status: u16, headers: &HeaderMap, body: String) -> OllamaError {
+        // Ollama responds with `{"error": "..."}`, without an error type or code.
+        let message = serde_json::from_str::<serde_json::Value>(&body)
+            .ok()
+            .and_then(|response| response["error"].as_str().map(ToString::to_string))
+            .unwrap_or_else(|| body.clone());
+        OllamaError::Http(Box::new(ApiErrorDetails {
+            status,
+            message,
+            error_type: None,
+            code: None,
+            request_id: None,
+            retry_after: net::retry_after(headers),
+            body,
+        }))
+    }
+
     fn parse_models_response(response: &str) -> Result<OllamaApiModelsMetadata, OllamaError> {
         let models: OllamaApiModelsMetadata =
             serde_json::from_str(response).map_err(|e| OllamaError::Parsing(e.to_string()))?;
@@ -98,12 +162,29 @@ impl LanguageModel for Ollama {
         &self,
         prompt: &str,
         system_prompt: &str,
-        _options: TextCompleteOptions,
+        options: TextCompleteOptions,
     ) -> Result<TextCompleteResponse, LanguageModelError> {
+        if !options.tools.is_empty() {
+            // Tools are only supported by the chat API, which keeps the conversation in the messages instead.
+            if options.context.is_some() {
+                return Err(LanguageModelError::UnsupportedFeature(
+                    "Ollama does not support tools with a conversation context, use `chat_complete` with the messages of the conversation instead".to_string(),
+                ));
+            }
+            let messages = [
+                ChatMessage::system(system_prompt.to_owned()),
+                ChatMessage::user(prompt.to_owned()),
+            ];
+            return self.chat_complete(&messages, options).await;
+        }
+
         let body = OllamaGenerateRequest {
             model: self.model.to_owned(),
             prompt: prompt.to_string(),
             system: Some(system_prompt.to_string()),
+            context: options.context,
+            format: Self::format(&options.response_format),
+            options: Some(Self::model_options(&options.generation)?),
             ..Default::default()
         };
 
@@ -115,10 +196,15 @@ impl LanguageModel for Ollama {
             .send()
             .await
             .map_err(|e| LanguageModelError::Ollama(OllamaError::ApiUnavailable(e.to_string())))?;
+        let status = response.status();
+        let headers = response.headers().clone();
         let body = response
             .text()
             .await
             .map_err(|e| LanguageModelError::Ollama(OllamaError::Api(e.to_string())))?;
+        if !status.is_success() {
+            return Err(Self::http_error(status.as_u16(), &headers, body).into());
+        }
         let ollama_response: OllamaGenerateResponse = serde_json::from_str(&body).map_err(|e| {
             LanguageModelError::Ollama(OllamaError::Parsing(format!(
                 "{}. Received response: {body}",
@@ -127,8 +213,18 @@ impl LanguageModel for Ollama {
         })?;
         match ollama_response {
             OllamaGenerateResponse::Success(success_response) => Ok(TextCompleteResponse {
+                usage: Self::usage(
+                    success_response.prompt_eval_count,
+                    success_response.eval_count,
+                ),
+                finish_reason: success_response
+                    .done_reason
+                    .as_deref()
+                    .map(Self::finish_reason),
                 text: success_response.response,
                 context: success_response.context,
+                tool_calls: Vec::new(),
+                served_by: Some(ServingModel::of(self)),
             }),
             OllamaGenerateResponse::Error(error_response) => Err(LanguageModelError::Ollama(
                 OllamaError::Api(format!("{error_response:?}")),
@@ -146,20 +242,53 @@ impl LanguageModel for Ollama {
             model: self.model.to_owned(),
             prompt: prompt.to_string(),
             stream: Some(true),
-            format: None,
+            format: Self::format(&options.response_format),
             images: None,
             system: Some(system_prompt.to_string()),
             keep_alive: Some("5m".to_string()),
             context: options.context,
+            options: Some(Self::model_options(&options.generation)?),
         };
 
         let url = format!("{}/api/generate", self.base_url);
-        let stream = SseClient::post(&url, Some(serde_json::to_string(&body).unwrap()));
-        let stream = stream.map(|event| {
-            let parsed_message = serde_json::from_str::<OllamaGenerateStreamItemResponse>(&event);
+        let stream = SseClient::post(
+            &url,
+            EventStreamFormat::NewlineDelimitedJson,
+            HeaderMap::new(),
+            Some(serde_json::to_string(&body).unwrap()),
+        );
+        let metadata = TextCompleteStreamMetadata::default();
+        metadata.set_served_by(ServingModel::of(self));
+        let stream_metadata = metadata.clone();
+        let stream = stream.map(move |event| {
+            let event = event.map_err(|e| match e {
+                NetError::Request(e) => LanguageModelError::Ollama(OllamaError::ApiUnavailable(e)),
+                NetError::Status {
+                    status,
+                    headers,
+                    body,
+                } => LanguageModelError::Ollama(Self::http_error(status, &headers, body)),
+                e => LanguageModelError::Ollama(OllamaError::Api(e.to_string())),
+            })?;
+            let parsed_message =
+                serde_json::from_str::<OllamaGenerateStreamItemResponse>(&event.data);
             match parsed_message {
                 Ok(message) => match message {
                     OllamaGenerateStreamItemResponse::Success(success_response) => {
+                        if success_response.done {
+                            if let Some(usage) = Self::usage(
+                                success_response.prompt_eval_count,
+                                success_response.eval_count,
+                            ) {
+                                stream_metadata.set_usage(usage);
+                            }
+                            if let Some(done_reason) = &success_response.done_reason {
+                                stream_metadata.set_finish_reason(Self::finish_reason(done_reason));
+                            }
+                            if let Some(context) = success_response.context {
+                                stream_metadata.set_context(context);
+                            }
+                        }
                         Ok(success_response.response)
                     }
                     OllamaGenerateStreamItemResponse::Error(error_response) => Err(
@@ -173,10 +302,124 @@ impl LanguageModel for Ollama {
         });
         let response = TextCompleteStreamResponse {
             stream: Box::pin(stream),
+            metadata,
         };
         Ok(response)
     }
 
+    async fn chat_complete(
+        &self,
+        messages: &[ChatMessage],
+        options: TextCompleteOptions,
+    ) -> Result<TextCompleteResponse, LanguageModelError> {
+        let body = OllamaChatRequest {
+            model: self.model.to_owned(),
+            messages: messages
+                .iter()
+                .map(|message| OllamaChatMessage {
+                    role: message.role.to_string(),
+                    content: message.content.to_owned(),
+                    tool_calls: (!message.tool_calls.is_empty()).then(|| {
+                        message
+                            .tool_calls
+                            .iter()
+                            .map(|tool_call| OllamaToolCall {
+                                function: OllamaToolCallFunction {
+                                    name: tool_call.name.to_owned(),
+                                    arguments: tool_call.arguments.clone(),
+                                },
+                            })
+                            .collect()
+                    }),
+                })
+                .collect(),
+            format: Self::format(&options.response_format),
+            options: Some(Self::model_options(&options.generation)?),
+            tools: (!options.tools.is_empty()).then(|| {
+                options
+                    .tools
+                    .iter()
+                    .map(|tool| OllamaTool {
+                        typ: "function".to_owned(),
+                        function: OllamaToolFunction {
+                            name: tool.name.to_owned(),
+                            description: tool.description.to_owned(),
+                            parameters: tool.parameters.clone(),
+                        },
+                    })
+                    .collect()
+            }),
+            ..Default::default()
+        };
+
+        let client = reqwest::Client::new();
+        let url = format!("{}/api/chat", self.base_url);
+        let response = client
+            .post(url)
+            .body(
+                serde_json::to_string(&body)
+                    .map_err(|e| OllamaError::Serialization(e.to_string()))?,
+            )
+            .send()
+            .await
+            .map_err(|e| LanguageModelError::Ollama(OllamaError::ApiUnavailable(e.to_string())))?;
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = response
+            .text()
+            .await
+            .map_err(|e| LanguageModelError::Ollama(OllamaError::Api(e.to_string())))?;
+        if !status.is_success() {
+            return Err(Self::http_error(status.as_u16(), &headers, body).into());
+        }
+        let ollama_response: OllamaChatResponse = serde_json::from_str(&body).map_err(|e| {
+            LanguageModelError::Ollama(OllamaError::Parsing(format!(
+                "{}. Received response: {body}",
+                e
+            )))
+        })?;
+        match ollama_response {
+            OllamaChatResponse::Success(success_response) => {
+                // Ollama does not assign identifiers to tool calls, so they are numbered by their position.
+                let tool_calls = success_response
+                    .message
+                    .tool_calls
+                    .unwrap_or_default()
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, tool_call)| ToolCall {
+                        id: format!("call_{i}"),
+                        name: tool_call.function.name,
+                        arguments: tool_call.function.arguments,
+                    })
+                    .collect::<Vec<_>>();
+                let finish_reason = if tool_calls.is_empty() {
+                    success_response
+                        .done_reason
+                        .as_deref()
+                        .map(Self::finish_reason)
+                } else {
+                    Some(FinishReason::ToolCalls)
+                };
+                Ok(TextCompleteResponse {
+                    text: success_response.message.content,
+                    // The chat API keeps the conversational memory in the messages themselves.
+                    context: None,
+                    usage: Self::usage(
+                        success_response.prompt_eval_count,
+                        success_response.eval_count,
+                    ),
+                    finish_reason,
+                    tool_calls,
+                    served_by: Some(ServingModel::of(self)),
+                })
+            }
+            OllamaChatResponse::Error(error_response) => Err(LanguageModelError::Ollama(
+                OllamaError::Api(format!("{error_response:?}")),
+            )),
+        }
+    }
+
     async fn generate_embedding(&self, prompt: &str) -> Result<Vec<f32>, LanguageModelError> {
         let client = reqwest::Client::new();
         let url = format!("{}/api/embeddings", self.base_url);
@@ -193,12 +436,17 @@ impl LanguageModel for Ollama {
             .send()
             .await
             .map_err(|e| OllamaError::ApiUnavailable(e.to_string()))?;
+        let status = response.status();
+        let headers = response.headers().clone();
         let body = response
             .text()
             .await
             .map_err(|e| OllamaError::Api(e.to_string()))?;
-        let response: OllamaEmbeddingsResponse =
-            serde_json::from_str(&body).map_err(|e| OllamaError::Parsing(e.to_string()))?;
+        if !status.is_success() {
+            return Err(Self::http_error(status.as_u16(), &headers, body).into());
+        }
+        let response: OllamaEmbeddingsResponse = serde_json::from_str(&body)
+            .map_err(|e| OllamaError::Parsing(format!("{e}. Received response: {body}")))?;
 
         Ok(response.embedding)
     }
@@ -215,3 +463,52 @@ impl LanguageModel for Ollama {
         self.embeddings_model.to_string()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::lm::{LanguageModelBuilder, OllamaBuilder, Tool};
+
+    use super::*;
+
+    #[test]
+    fn test_model_options() {
+        let generation = GenerationOptions {
+            temperature: Some(1.5),
+            max_tokens: Some(100),
+            seed: Some(42),
+            ..Default::default()
+        };
+        let options = Ollama::model_options(&generation).unwrap();
+        assert_eq!(options.temperature, Some(1.5));
+        assert_eq!(options.num_predict, Some(100));
+        assert_eq!(options.seed, Some(42));
+
+        let generation = GenerationOptions {
+            top_p: Some(1.5),
+            ..Default::default()
+        };
+        assert!(matches!(
+            Ollama::model_options(&generation),
+            Err(LanguageModelError::Configuration(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_text_complete_tools_with_context() {
+        let lm = OllamaBuilder::new().try_build().unwrap();
+        let options = TextCompleteOptions {
+            context: Some(vec![1, 2, 3]),
+            tools: vec![Tool {
+                name: "get_weather".to_string(),
+                description: "Returns the weather in a city".to_string(),
+                parameters: serde_json::json!({ "type": "object" }),
+            }],
+            ..Default::default()
+        };
+        let result = lm.text_complete("Weather in Paris?", "", options).await;
+        assert!(matches!(
+            result,
+            Err(LanguageModelError::UnsupportedFeature(_))
+        ));
+    }
+}
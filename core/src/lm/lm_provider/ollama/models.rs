@@ -90,8 +90,8 @@ pub struct OllamaGenerateRequest {
     /// Optional list of base64-encoded images (for multimodal models such as `llava`)
     pub images: Option<Vec<String>>,
 
-    /// Optional format to use for the response (currently only "json" is supported)
-    pub format: Option<String>,
+    /// Optional format to use for the response ("json" or a JSON schema)
+    pub format: Option<serde_json::Value>,
 
     /// Optional flag that controls whether the response is streamed or not (defaults to true).
     /// If `false`` the response will be returned as a single response object, rather than a stream of objects
@@ -102,6 +102,10 @@ pub struct OllamaGenerateRequest {
 
     /// Controls how long the model will stay loaded into memory following the request (default: 5m)
     pub keep_alive: Option<String>,
+
+    /// Additional model parameters (e.g., temperature), overriding what is defined in the Modelfile
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub options: Option<OllamaModelOptions>,
 }
 
 impl Default for OllamaGenerateRequest {
@@ -115,10 +119,37 @@ impl Default for OllamaGenerateRequest {
             system: Some("You are a helpful assistant".to_string()),
             keep_alive: Some("5m".to_string()),
             context: None,
+            options: None,
         }
     }
 }
 
+/// Model parameters which can be set per request.
+///
+/// Referenced from the Ollama documentation [here](https://github.com/ollama/ollama/blob/fedf71635ec77644f8477a86c6155217d9213a11/docs/modelfile.md#valid-parameters-and-values).
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct OllamaModelOptions {
+    /// The temperature of the model (default: 0.8)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+
+    /// Maximum number of tokens to predict (default: 128, -1 for infinite generation)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_predict: Option<usize>,
+
+    /// Works together with top-k, a higher value will lead to more diverse text (default: 0.9)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+
+    /// Stop sequences to use
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Vec<String>>,
+
+    /// Random number seed to use for generation (default: 0)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+}
+
 /// Response from the Ollama API for generating a response.
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(untagged)]
@@ -144,6 +175,14 @@ pub struct OllamaGenerateResponseSuccess {
 
     /// The duration of the response in nanoseconds
     pub total_duration: usize,
+    /// The reason the generation stopped (e.g., "stop" or "length")
+    pub done_reason: Option<String>,
+
+    /// Number of tokens in the prompt
+    pub prompt_eval_count: Option<usize>,
+
+    /// Number of tokens in the response
+    pub eval_count: Option<usize>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -169,6 +208,21 @@ pub struct OllamaGenerateStreamItemResponseSuccess {
 
     /// The response to the prompt
     pub response: String,
+
+    /// Whether this is the final item of the stream
+    #[serde(default)]
+    pub done: bool,
+    /// The reason the generation stopped (e.g., "stop" or "length")
+    pub done_reason: Option<String>,
+
+    /// Number of tokens in the prompt
+    pub prompt_eval_count: Option<usize>,
+
+    /// Number of tokens in the response
+    pub eval_count: Option<usize>,
+
+    /// An encoding of the conversation (sent in the final item), which can be sent in the next request
+    pub context: Option<Vec<i64>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -177,6 +231,135 @@ pub struct OllamaGenerateStreamItemResponseError {
     pub error: String,
 }
 
+/// Request for generating the next message in a chat from the Ollama API.
+///
+/// Referenced from the Ollama API documentation [here](https://github.com/ollama/ollama/blob/fedf71635ec77644f8477a86c6155217d9213a11/docs/api.md#generate-a-chat-completion).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OllamaChatRequest {
+    /// Model identifier (e.g., "mistral:latest")
+    pub model: String,
+
+    /// The messages of the chat, this can be used to keep a chat memory
+    pub messages: Vec<OllamaChatMessage>,
+
+    /// Optional format to use for the response ("json" or a JSON schema)
+    pub format: Option<serde_json::Value>,
+
+    /// Optional flag that controls whether the response is streamed or not (defaults to true).
+    /// If `false` the response will be returned as a single response object, rather than a stream of objects
+    pub stream: Option<bool>,
+
+    /// Controls how long the model will stay loaded into memory following the request (default: 5m)
+    pub keep_alive: Option<String>,
+
+    /// Additional model parameters (e.g., temperature), overriding what is defined in the Modelfile
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub options: Option<OllamaModelOptions>,
+
+    /// Tools for the model to use, if supported (requires `stream` to be `false`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<OllamaTool>>,
+}
+
+impl Default for OllamaChatRequest {
+    fn default() -> Self {
+        Self {
+            model: ollama_model::CODESTRAL.to_string(),
+            messages: Vec::new(),
+            format: None,
+            stream: Some(false),
+            keep_alive: Some("5m".to_string()),
+            options: None,
+            tools: None,
+        }
+    }
+}
+
+/// A single message in a chat with the Ollama API.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OllamaChatMessage {
+    /// The role of the message, either "system", "user", "assistant" or "tool"
+    pub role: String,
+
+    /// The content of the message
+    pub content: String,
+
+    /// Tools that the model wants to use
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<OllamaToolCall>>,
+}
+
+/// A tool which the model may use.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OllamaTool {
+    /// Type of the tool (currently only "function" is supported)
+    #[serde(rename = "type")]
+    pub typ: String,
+
+    /// The function definition
+    pub function: OllamaToolFunction,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OllamaToolFunction {
+    /// Name of the function
+    pub name: String,
+
+    /// Description of the function
+    pub description: String,
+
+    /// JSON schema of the parameters of the function
+    pub parameters: serde_json::Value,
+}
+
+/// A tool call requested by the model.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OllamaToolCall {
+    /// The function to call
+    pub function: OllamaToolCallFunction,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OllamaToolCallFunction {
+    /// Name of the function
+    pub name: String,
+
+    /// Arguments for the function
+    pub arguments: serde_json::Value,
+}
+
+/// Response from the Ollama API for generating the next message in a chat.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum OllamaChatResponse {
+    Success(OllamaChatResponseSuccess),
+    Error(OllamaGenerateResponseError),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[allow(dead_code)]
+pub struct OllamaChatResponseSuccess {
+    /// Model identifier (e.g., "mistral:latest")
+    pub model: String,
+
+    /// Time at which the response was generated (ISO 8601 format)
+    pub created_at: String,
+
+    /// The message generated by the model
+    pub message: OllamaChatMessage,
+
+    /// The duration of the response in nanoseconds
+    pub total_duration: usize,
+    /// The reason the generation stopped (e.g., "stop" or "length")
+    pub done_reason: Option<String>,
+
+    /// Number of tokens in the prompt
+    pub prompt_eval_count: Option<usize>,
+
+    /// Number of tokens in the response
+    pub eval_count: Option<usize>,
+}
+
 /// Request for generating an embedding from the Ollama API.
 /// Referenced from the Ollama API documentation [here](https://github.com/ollama/ollama/blob/fedf71635ec77644f8477a86c6155217d9213a11/docs/api.md#generate-embeddings).
 ///
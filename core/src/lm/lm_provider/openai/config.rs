@@ -4,3 +4,5 @@ use super::{openai_embedding_model, openai_model};
 pub const DEFAULT_MODEL: &str = openai_model::GPT_4O_MINI;
 /// Default model to use for embedding generation.
 pub const DEFAULT_EMBEDDINGS_MODEL: &str = openai_embedding_model::TEXT_EMBEDDING_ADA_002;
+/// Default API endpoint for the OpenAI API.
+pub const DEFAULT_API_ENDPOINT: &str = "https://api.openai.com/v1";
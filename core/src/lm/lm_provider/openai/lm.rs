@@ -1,22 +1,32 @@
+use async_gen::AsyncIter;
 use async_trait::async_trait;
 use lm::{
-    error::LanguageModelError,
+    error::{ApiErrorDetails, LanguageModelError},
     models::{
-        TextCompleteOptions, TextCompleteResponse, TextCompleteStreamOptions,
-        TextCompleteStreamResponse,
+        ChatMessage, ChatRole, FinishReason, GenerationOptions, ResponseFormat, ServingModel,
+        TextCompleteOptions, TextCompleteResponse, TextCompleteStreamMetadata,
+        TextCompleteStreamOptions, TextCompleteStreamResponse, TokenUsage,
     },
-    LanguageModel, LanguageModelProvider,
+    LanguageModel, LanguageModelProvider, ToolCall,
 };
+use net::{EventStreamFormat, NetError, SseClient};
 use openai_api_rs::v1::{
-    api::OpenAIClient,
     chat_completion::{self, ChatCompletionRequest},
-    embedding::EmbeddingRequest,
+    embedding::{EmbeddingRequest, EmbeddingResponse},
 };
+use reqwest::header::{self, HeaderMap};
 use thiserror::Error;
-use tokio_stream::{self as stream};
+use tokio_stream::StreamExt;
 
 use crate::*;
 
+use super::{
+    config::DEFAULT_API_ENDPOINT, OpenAiApiError, OpenAiApiErrorBody, OpenAiChatCompletionChunk,
+};
+
+/// Maximum number of stop sequences accepted by the OpenAI API.
+const MAX_STOP_SEQUENCES: usize = 4;
+
 #[derive(Debug, Clone)]
 pub struct OpenAi {
     pub api_endpoint: Option<String>,
@@ -30,6 +40,9 @@ pub enum OpenAiError {
     #[error("Unexpected response from API. Error: {0}")]
     Api(String),
 
+    #[error("OpenAI API returned an error: {0}")]
+    Http(Box<ApiErrorDetails>),
+
     #[error("Configuration error: {0}")]
     Configuration(String),
 
@@ -40,88 +53,391 @@ pub enum OpenAiError {
     ApiUnavailable(String),
 }
 
+impl OpenAi {
+    /// Returns the error of a response with an unsuccessful HTTP status.
+    fn http_error(status: u16, headers: &HeaderMap, body: String) -> OpenAiError {
+        let error = serde_json::from_str::<OpenAiApiError>(&body)
+            .ok()
+            .map(|error_response| error_response.error);
+        OpenAiError::Http(Box::new(ApiErrorDetails {
+            status,
+            message: error
+                .as_ref()
+                .map_or_else(|| body.clone(), |error| error.message.clone()),
+            error_type: error.as_ref().and_then(|error| error.typ.clone()),
+            code: error.and_then(|error| error.code),
+            request_id: net::header_value(headers, "x-request-id"),
+            retry_after: net::retry_after(headers),
+            body,
+        }))
+    }
+
+    fn headers(&self) -> Result<HeaderMap, LanguageModelError> {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            format!("Bearer {}", self.api_key)
+                .parse()
+                .map_err(|_| OpenAiError::Configuration("Invalid API key".to_string()))?,
+        );
+        Ok(headers)
+    }
+
+    /// Returns the error of an error event in a streamed response.
+    ///
+    /// The response itself was successful, so the status is the one documented for the error code or type
+    /// (see the OpenAI API documentation [here](https://platform.openai.com/docs/guides/error-codes)).
+    fn stream_error(error: OpenAiApiErrorBody) -> OpenAiError {
+        let code = error.code.as_deref().unwrap_or_default();
+        let error_type = error.typ.as_deref().unwrap_or_default();
+        let status = match (code, error_type) {
+            ("invalid_api_key", _) | (_, "authentication_error") => 401,
+            (_, "permission_error") => 403,
+            ("model_not_found", _) | (_, "not_found_error") => 404,
+            ("rate_limit_exceeded" | "insufficient_quota", _)
+            | (_, "rate_limit_error" | "insufficient_quota" | "requests" | "tokens") => 429,
+            ("context_length_exceeded", _) | (_, "invalid_request_error") => 400,
+            _ => 500,
+        };
+        OpenAiError::Http(Box::new(ApiErrorDetails {
+            status,
+            body: serde_json::to_string(&error).unwrap_or_default(),
+            message: error.message,
+            error_type: error.typ,
+            code: error.code,
+            request_id: None,
+            retry_after: None,
+        }))
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!(
+            "{}/{path}",
+            self.api_endpoint.as_deref().unwrap_or(DEFAULT_API_ENDPOINT)
+        )
+    }
+
+    /// Sends a JSON request to the API, and returns the body of the (successful) response.
+    async fn post(&self, path: &str, body: String) -> Result<String, LanguageModelError> {
+        let response = reqwest::Client::new()
+            .post(self.url(path))
+            .headers(self.headers()?)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| LanguageModelError::OpenAi(OpenAiError::ApiUnavailable(e.to_string())))?;
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = response
+            .text()
+            .await
+            .map_err(|e| LanguageModelError::OpenAi(OpenAiError::Api(e.to_string())))?;
+        if !status.is_success() {
+            return Err(Self::http_error(status.as_u16(), &headers, body).into());
+        }
+        Ok(body)
+    }
+
+    /// Sets the `response_format` of the request, falling back to JSON mode for schemas which the API does not
+    /// support (i.e., which are not rooted in an object, such as a `oneOf` of the response variants) and for models
+    /// without structured outputs, and to no format for models without JSON mode (e.g., `gpt-4`).
+    fn set_response_format(
+        model: &str,
+        body: &mut serde_json::Value,
+        response_format: &ResponseFormat,
+    ) {
+        let supports_json_mode =
+            !(model == "gpt-4" || model.starts_with("gpt-4-0") || model.starts_with("gpt-4-32k"));
+        let supports_json_schema =
+            supports_json_mode && !(model.starts_with("gpt-3.5") || model.starts_with("gpt-4-"));
+        match response_format {
+            ResponseFormat::Text => {}
+            ResponseFormat::JsonSchema(schema)
+                if supports_json_schema
+                    && schema["type"] == "object"
+                    && schema.get("oneOf").is_none()
+                    && schema.get("anyOf").is_none() =>
+            {
+                // Strict mode is not used, as it requires all properties to be required.
+                body["response_format"] = serde_json::json!({
+                    "type": "json_schema",
+                    "json_schema": { "name": "response", "schema": schema, "strict": false }
+                });
+            }
+            ResponseFormat::Json | ResponseFormat::JsonSchema(_) if supports_json_mode => {
+                body["response_format"] = serde_json::json!({ "type": "json_object" });
+            }
+            ResponseFormat::Json | ResponseFormat::JsonSchema(_) => {}
+        }
+    }
+
+    fn finish_reason(finish_reason: &str) -> FinishReason {
+        match finish_reason {
+            "stop" => FinishReason::Stop,
+            "length" => FinishReason::Length,
+            "content_filter" => FinishReason::ContentFilter,
+            "tool_calls" => FinishReason::ToolCalls,
+            other => FinishReason::Other(other.to_owned()),
+        }
+    }
+
+    fn chat_completion_request(
+        model: &str,
+        messages: &[ChatMessage],
+        generation: &GenerationOptions,
+    ) -> Result<ChatCompletionRequest, LanguageModelError> {
+        generation.validate_ranges("OpenAI", Some(2.0))?;
+        if generation
+            .stop
+            .as_ref()
+            .is_some_and(|stop| stop.len() > MAX_STOP_SEQUENCES)
+        {
+            return Err(LanguageModelError::UnsupportedFeature(format!(
+                "OpenAI supports up to {MAX_STOP_SEQUENCES} stop sequences"
+            )));
+        }
+
+        let messages = messages
+            .iter()
+            .map(|message| chat_completion::ChatCompletionMessage {
+                role: match message.role {
+                    ChatRole::System => chat_completion::MessageRole::system,
+                    ChatRole::User => chat_completion::MessageRole::user,
+                    ChatRole::Assistant => chat_completion::MessageRole::assistant,
+                    ChatRole::Tool => chat_completion::MessageRole::tool,
+                },
+                content: chat_completion::Content::Text(message.content.to_owned()),
+                name: None,
+                tool_calls: (!message.tool_calls.is_empty()).then(|| {
+                    message
+                        .tool_calls
+                        .iter()
+                        .map(|tool_call| chat_completion::ToolCall {
+                            id: tool_call.id.to_owned(),
+                            r#type: "function".to_owned(),
+                            function: chat_completion::ToolCallFunction {
+                                name: Some(tool_call.name.to_owned()),
+                                arguments: Some(tool_call.arguments.to_string()),
+                            },
+                        })
+                        .collect()
+                }),
+                tool_call_id: message.tool_call_id.clone(),
+            })
+            .collect::<Vec<_>>();
+        let mut req = ChatCompletionRequest::new(model.to_owned(), messages);
+        req.temperature = generation.temperature.map(f64::from);
+        req.max_tokens = generation.max_tokens.map(|max_tokens| max_tokens as i64);
+        req.top_p = generation.top_p.map(f64::from);
+        req.stop = generation.stop.clone();
+        req.seed = generation.seed.map(|seed| seed as i64);
+        Ok(req)
+    }
+}
+
 #[async_trait]
 impl LanguageModel for OpenAi {
     async fn text_complete(
         &self,
         prompt: &str,
         system_prompt: &str,
-        _options: TextCompleteOptions,
+        options: TextCompleteOptions,
     ) -> Result<TextCompleteResponse, LanguageModelError> {
-        let mut client = OpenAIClient::new(self.api_key.to_owned());
-
-        if let Some(api_endpoint) = self.api_endpoint.clone() {
-            client.api_endpoint = api_endpoint;
-        }
-
         let messages = vec![
-            chat_completion::ChatCompletionMessage {
-                role: chat_completion::MessageRole::system,
-                content: chat_completion::Content::Text(system_prompt.to_owned()),
-                name: None,
-                tool_calls: None,
-                tool_call_id: None,
-            },
-            chat_completion::ChatCompletionMessage {
-                role: chat_completion::MessageRole::user,
-                content: chat_completion::Content::Text(prompt.to_owned()),
-                name: None,
-                tool_calls: None,
-                tool_call_id: None,
-            },
+            ChatMessage::system(system_prompt),
+            ChatMessage::user(prompt),
         ];
-        // TODO: Support customization of max tokens and temperature.
-        let req = ChatCompletionRequest::new(self.model.to_owned(), messages);
-
-        let result = client
-            .chat_completion(req)
-            .await
-            .map_err(|e| LanguageModelError::OpenAi(OpenAiError::Api(e.to_string())))?;
-        let completion = result
-            .choices
-            .first()
-            .unwrap()
-            .message
-            .content
-            .clone()
-            .unwrap();
-        Ok(TextCompleteResponse {
-            text: completion,
-            // TODO: Support context.
-            context: None,
-        })
+        self.chat_complete(&messages, options).await
     }
 
     async fn text_complete_stream(
         &self,
         prompt: &str,
         system_prompt: &str,
-        _options: TextCompleteStreamOptions,
+        options: TextCompleteStreamOptions,
     ) -> Result<TextCompleteStreamResponse, LanguageModelError> {
-        // TODO: Support streaming - currently it just sends a single message.
-        let text_completion_response = self
-            .text_complete(prompt, system_prompt, TextCompleteOptions { context: None })
-            .await?;
+        let messages = vec![
+            ChatMessage::system(system_prompt),
+            ChatMessage::user(prompt),
+        ];
+        let req = Self::chat_completion_request(&self.model, &messages, &options.generation)?
+            .stream(true);
+        let mut body =
+            serde_json::to_value(&req).map_err(|e| OpenAiError::Serialization(e.to_string()))?;
+        // Request the token usage, which is sent in a final chunk with no choices.
+        body["stream_options"] = serde_json::json!({ "include_usage": true });
+        Self::set_response_format(&self.model, &mut body, &options.response_format);
+        let body = body.to_string();
+
+        let headers = self.headers()?;
+        let url = self.url("chat/completions");
+        let mut events = Box::pin(SseClient::post(
+            &url,
+            EventStreamFormat::EventStream,
+            headers,
+            Some(body),
+        ));
+        let metadata = TextCompleteStreamMetadata::default();
+        metadata.set_served_by(ServingModel::of(self));
+        let stream_metadata = metadata.clone();
+        let stream = AsyncIter::from(async_gen::gen! {
+            while let Some(event) = events.next().await {
+                let event = match event {
+                    Ok(event) => event,
+                    Err(NetError::Request(e)) => {
+                        yield Err(LanguageModelError::OpenAi(OpenAiError::ApiUnavailable(e)));
+                        return;
+                    }
+                    Err(NetError::Status { status, headers, body }) => {
+                        yield Err(LanguageModelError::OpenAi(Self::http_error(status, &headers, body)));
+                        return;
+                    }
+                    Err(e) => {
+                        yield Err(LanguageModelError::OpenAi(OpenAiError::Api(e.to_string())));
+                        return;
+                    }
+                };
+                if event.data == "[DONE]" {
+                    return;
+                }
+                match serde_json::from_str::<OpenAiChatCompletionChunk>(&event.data) {
+                    Ok(OpenAiChatCompletionChunk::Success(chunk)) => {
+                        if let Some(usage) = chunk.usage {
+                            stream_metadata.set_usage(TokenUsage {
+                                prompt_tokens: usage.prompt_tokens,
+                                completion_tokens: usage.completion_tokens,
+                            });
+                        }
+                        let Some(choice) = chunk.choices.into_iter().next() else {
+                            continue;
+                        };
+                        if let Some(finish_reason) = &choice.finish_reason {
+                            stream_metadata.set_finish_reason(Self::finish_reason(finish_reason));
+                        }
+                        match choice.delta.content {
+                            Some(content) if !content.is_empty() => {
+                                yield Ok(content);
+                            }
+                            _ => {}
+                        }
+                    }
+                    Ok(OpenAiChatCompletionChunk::Error(error_response)) => {
+                        yield Err(LanguageModelError::OpenAi(Self::stream_error(error_response.error)));
+                        return;
+                    }
+                    Err(e) => {
+                        yield Err(LanguageModelError::OpenAi(OpenAiError::Serialization(format!(
+                            "{e}. Received event: {}",
+                            event.data
+                        ))));
+                        return;
+                    }
+                }
+            }
+        });
         Ok(TextCompleteStreamResponse {
-            stream: Box::pin(stream::once(Ok(text_completion_response.text))),
+            stream: Box::pin(stream),
+            metadata,
         })
     }
 
-    async fn generate_embedding(&self, prompt: &str) -> Result<Vec<f32>, LanguageModelError> {
-        let client = OpenAIClient::new(self.api_key.to_owned());
-
-        let resp = client
-            .embedding(EmbeddingRequest {
-                model: self.embeddings_model.to_owned(),
-                input: prompt.to_owned(),
-                dimensions: None,
-                user: None,
+    async fn chat_complete(
+        &self,
+        messages: &[ChatMessage],
+        options: TextCompleteOptions,
+    ) -> Result<TextCompleteResponse, LanguageModelError> {
+        let req = Self::chat_completion_request(&self.model, messages, &options.generation)?;
+        let mut body =
+            serde_json::to_value(&req).map_err(|e| OpenAiError::Serialization(e.to_string()))?;
+        if !options.tools.is_empty() {
+            // The tool definitions of `openai_api_rs` cannot represent arbitrary JSON schemas,
+            // so they are added to the request body directly.
+            body["tools"] = options
+                .tools
+                .iter()
+                .map(|tool| {
+                    serde_json::json!({
+                        "type": "function",
+                        "function": {
+                            "name": tool.name,
+                            "description": tool.description,
+                            "parameters": tool.parameters,
+                        }
+                    })
+                })
+                .collect();
+        }
+
+        Self::set_response_format(&self.model, &mut body, &options.response_format);
+
+        let body = self.post("chat/completions", body.to_string()).await?;
+        let result: chat_completion::ChatCompletionResponse = serde_json::from_str(&body)
+            .map_err(|e| OpenAiError::Serialization(format!("{e}. Received response: {body}")))?;
+
+        let choice = result
+            .choices
+            .into_iter()
+            .next()
+            .ok_or(OpenAiError::Api("Response choices are empty".to_string()))?;
+        let tool_calls = choice
+            .message
+            .tool_calls
+            .unwrap_or_default()
+            .into_iter()
+            .map(|tool_call| {
+                let arguments = tool_call.function.arguments.unwrap_or_default();
+                ToolCall {
+                    id: tool_call.id,
+                    name: tool_call.function.name.unwrap_or_default(),
+                    arguments: serde_json::from_str(&arguments)
+                        .unwrap_or(serde_json::Value::String(arguments)),
+                }
             })
-            .await
-            .map_err(|e| LanguageModelError::OpenAi(OpenAiError::Api(e.to_string())))?;
+            .collect::<Vec<_>>();
+        // The content may be empty if the model only requested tool calls.
+        let completion = match choice.message.content {
+            Some(content) => content,
+            None if !tool_calls.is_empty() => String::new(),
+            None => return Err(OpenAiError::Api("Response content is empty".to_string()).into()),
+        };
+        let finish_reason = choice
+            .finish_reason
+            .map(|finish_reason| match finish_reason {
+                chat_completion::FinishReason::stop => FinishReason::Stop,
+                chat_completion::FinishReason::length => FinishReason::Length,
+                chat_completion::FinishReason::content_filter => FinishReason::ContentFilter,
+                chat_completion::FinishReason::tool_calls => FinishReason::ToolCalls,
+                other => FinishReason::Other(format!("{other:?}")),
+            });
+        Ok(TextCompleteResponse {
+            text: completion,
+            // TODO: Support context.
+            context: None,
+            usage: Some(TokenUsage {
+                prompt_tokens: result.usage.prompt_tokens.max(0) as usize,
+                completion_tokens: result.usage.completion_tokens.max(0) as usize,
+            }),
+            finish_reason,
+            tool_calls,
+            served_by: Some(ServingModel::of(self)),
+        })
+    }
+
+    async fn generate_embedding(&self, prompt: &str) -> Result<Vec<f32>, LanguageModelError> {
+        let req = EmbeddingRequest::new(self.embeddings_model.to_owned(), vec![prompt.to_owned()]);
+        let body =
+            serde_json::to_string(&req).map_err(|e| OpenAiError::Serialization(e.to_string()))?;
+        let body = self.post("embeddings", body).await?;
+        let result: EmbeddingResponse = serde_json::from_str(&body)
+            .map_err(|e| OpenAiError::Serialization(format!("{e}. Received response: {body}")))?;
 
-        let data = resp.data.first().expect("Embedding data not found");
-        Ok(data.embedding.clone())
+        let data = result
+            .data
+            .into_iter()
+            .next()
+            .ok_or(OpenAiError::Api("Embedding data is empty".to_string()))?;
+        Ok(data.embedding)
     }
 
     fn provider(&self) -> LanguageModelProvider {
@@ -136,3 +452,92 @@ impl LanguageModel for OpenAi {
         self.embeddings_model.to_string()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use lm::ApiErrorKind;
+
+    use super::*;
+
+    #[test]
+    fn test_stream_error() {
+        let error = serde_json::from_str::<OpenAiApiError>(
+            r#"{"error": {"message": "Rate limit reached", "type": "requests", "code": "rate_limit_exceeded"}}"#,
+        )
+        .unwrap();
+        let error = LanguageModelError::OpenAi(OpenAi::stream_error(error.error));
+        let details = error.api_error_details().unwrap();
+        assert_eq!(details.status, 429);
+        assert_eq!(details.kind(), ApiErrorKind::RateLimited);
+        assert_eq!(details.code.as_deref(), Some("rate_limit_exceeded"));
+        assert!(error.is_retryable());
+    }
+
+    #[test]
+    fn test_chat_completion_request_generation() {
+        let messages = [ChatMessage::user("Hello")];
+        let generation = GenerationOptions {
+            temperature: Some(1.5),
+            top_p: Some(0.9),
+            seed: Some(42),
+            ..Default::default()
+        };
+        let req = OpenAi::chat_completion_request("gpt-4o-mini", &messages, &generation).unwrap();
+        assert_eq!(req.temperature, Some(1.5));
+        assert_eq!(req.seed, Some(42));
+
+        let generation = GenerationOptions {
+            temperature: Some(2.5),
+            ..Default::default()
+        };
+        assert!(matches!(
+            OpenAi::chat_completion_request("gpt-4o-mini", &messages, &generation),
+            Err(LanguageModelError::Configuration(_))
+        ));
+
+        let generation = GenerationOptions {
+            stop: Some(
+                vec!["a", "b", "c", "d", "e"]
+                    .into_iter()
+                    .map(String::from)
+                    .collect(),
+            ),
+            ..Default::default()
+        };
+        assert!(matches!(
+            OpenAi::chat_completion_request("gpt-4o-mini", &messages, &generation),
+            Err(LanguageModelError::UnsupportedFeature(_))
+        ));
+    }
+
+    #[test]
+    fn test_set_response_format() {
+        let response_format = |model: &str, response_format: ResponseFormat| {
+            let mut body = serde_json::json!({});
+            OpenAi::set_response_format(model, &mut body, &response_format);
+            body["response_format"]["type"].clone()
+        };
+        let object_schema = serde_json::json!({ "type": "object", "properties": {} });
+        let variants_schema = serde_json::json!({ "oneOf": [object_schema.clone()] });
+
+        assert_eq!(
+            response_format(
+                "gpt-4o-mini",
+                ResponseFormat::JsonSchema(object_schema.clone())
+            ),
+            "json_schema"
+        );
+        assert_eq!(
+            response_format("gpt-4o-mini", ResponseFormat::JsonSchema(variants_schema)),
+            "json_object"
+        );
+        assert_eq!(
+            response_format("gpt-3.5-turbo", ResponseFormat::JsonSchema(object_schema)),
+            "json_object"
+        );
+        assert_eq!(
+            response_format("gpt-4", ResponseFormat::Json),
+            serde_json::Value::Null
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use serde::{Deserialize, Serialize};
+
 pub mod openai_model {
     pub const GPT_3_5_TURBO: &str = "gpt-3.5-turbo";
     pub const GPT_4: &str = "gpt-4";
@@ -13,3 +15,80 @@ pub mod openai_embedding_model {
     pub const TEXT_EMBEDDING_3_LARGE: &str = "text-embedding-3-large";
     pub const TEXT_EMBEDDING_3_LARGE_DIMENSIONS: usize = 3072;
 }
+
+/// A single chunk of a streamed chat completion (sent as the `data` of a server-sent event).
+/// Referenced from the OpenAI API documentation [here](https://platform.openai.com/docs/api-reference/chat/streaming).
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum OpenAiChatCompletionChunk {
+    Success(OpenAiChatCompletionChunkSuccess),
+    Error(OpenAiApiError),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[allow(dead_code)]
+pub struct OpenAiChatCompletionChunkSuccess {
+    /// Unique identifier for the chat completion (each chunk has the same ID).
+    pub id: String,
+
+    /// The model used to generate the completion.
+    pub model: String,
+
+    /// List of completion choices (may be empty for the last chunk, if usage was requested).
+    pub choices: Vec<OpenAiChatCompletionChunkChoice>,
+    /// Token usage of the entire request, only set on the last chunk (if requested with `stream_options`).
+    pub usage: Option<OpenAiUsage>,
+}
+
+/// Token usage of a chat completion.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OpenAiUsage {
+    /// Number of tokens in the prompt.
+    pub prompt_tokens: usize,
+
+    /// Number of tokens in the generated completion.
+    pub completion_tokens: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[allow(dead_code)]
+pub struct OpenAiChatCompletionChunkChoice {
+    /// Index of the choice in the list of choices.
+    pub index: usize,
+
+    /// The delta generated by the streamed model response.
+    pub delta: OpenAiChatCompletionChunkDelta,
+
+    /// The reason the model stopped generating tokens (e.g., "stop" or "length"), only set on the last chunk.
+    pub finish_reason: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OpenAiChatCompletionChunkDelta {
+    /// The role of the author of this message (only set on the first chunk).
+    pub role: Option<String>,
+
+    /// The contents of the chunk.
+    pub content: Option<String>,
+}
+
+/// Response from the OpenAI API which indicates an error.
+/// Referenced from the OpenAI API documentation [here](https://platform.openai.com/docs/guides/error-codes).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OpenAiApiError {
+    /// Error details.
+    pub error: OpenAiApiErrorBody,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OpenAiApiErrorBody {
+    /// Error message.
+    pub message: String,
+
+    /// Type of the error (e.g., "invalid_request_error").
+    #[serde(rename = "type")]
+    pub typ: Option<String>,
+
+    /// Error code (e.g., "invalid_api_key").
+    pub code: Option<String>,
+}
@@ -3,10 +3,16 @@
 
 mod builder;
 mod error;
+mod fallback;
 mod lm_provider;
 mod models;
+mod retry;
+mod tool;
 
 pub use builder::*;
 pub use error::*;
+pub use fallback::*;
 pub use lm_provider::*;
 pub use models::*;
+pub use retry::*;
+pub use tool::*;
@@ -1,12 +1,16 @@
 #![allow(dead_code)]
 
-use std::pin::Pin;
+use std::{
+    pin::Pin,
+    sync::{Arc, Mutex},
+};
 
 use async_trait::async_trait;
 use dyn_clone::DynClone;
-use tokio_stream::Stream;
+use serde::{Deserialize, Serialize};
+use tokio_stream::{Stream, StreamExt};
 
-use super::{error::LanguageModelError, LanguageModelProvider};
+use super::{error::LanguageModelError, LanguageModelProvider, Tool, ToolCall};
 
 /// A trait for language model providers which implements text completion, embeddings, etc.
 ///
@@ -58,6 +62,28 @@ pub trait LanguageModel: DynClone + Send + Sync {
     /// A [Result] containing the embedding or an error if there was a problem.
     async fn generate_embedding(&self, prompt: &str) -> Result<Vec<f32>, LanguageModelError>;
 
+    /// Generates a response from the LLM for a multi-turn conversation.
+    ///
+    /// By default, the system messages are joined into the system prompt and the rest of the conversation
+    /// is flattened into a transcript (see [`ChatMessage::transcript`]), which is sent to [`Self::text_complete`].
+    /// Providers with a native chat API should override this.
+    ///
+    /// # Arguments
+    /// * `messages` - The messages of the conversation so far (including an optional system message).
+    /// * `options` - The options for the generation.
+    ///
+    /// # Returns
+    /// A [Result] containing the response from the LLM or an error if there was a problem.
+    ///
+    async fn chat_complete(
+        &self,
+        messages: &[ChatMessage],
+        options: TextCompleteOptions,
+    ) -> Result<TextCompleteResponse, LanguageModelError> {
+        let (system_prompt, prompt) = ChatMessage::transcript(messages);
+        self.text_complete(&prompt, &system_prompt, options).await
+    }
+
     /// Returns the provider of the LLM.
     fn provider(&self) -> LanguageModelProvider;
 
@@ -68,16 +94,224 @@ pub trait LanguageModel: DynClone + Send + Sync {
     fn embedding_model_name(&self) -> String;
 }
 
+dyn_clone::clone_trait_object!(LanguageModel);
+
+/// The role of the author of a [`ChatMessage`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChatRole {
+    /// Instructions for the model (i.e., the system prompt).
+    #[serde(rename = "system")]
+    System,
+    /// A message sent by the user.
+    #[serde(rename = "user")]
+    User,
+    /// A message previously generated by the model.
+    #[serde(rename = "assistant")]
+    Assistant,
+    /// The result of a [`ToolCall`] requested by the model.
+    #[serde(rename = "tool")]
+    Tool,
+}
+
+impl std::fmt::Display for ChatRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChatRole::System => write!(f, "system"),
+            ChatRole::User => write!(f, "user"),
+            ChatRole::Assistant => write!(f, "assistant"),
+            ChatRole::Tool => write!(f, "tool"),
+        }
+    }
+}
+
+/// A single message in a conversation with a language model.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    /// The author of the message.
+    pub role: ChatRole,
+    /// The text content of the message.
+    pub content: String,
+    /// Tool calls requested by the model (only for assistant messages).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    /// Identifier of the tool call this message is the result of (only for tool messages).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+}
+
+impl ChatMessage {
+    /// Creates a new system message.
+    pub fn system(content: impl Into<String>) -> Self {
+        Self {
+            role: ChatRole::System,
+            content: content.into(),
+            tool_calls: Vec::new(),
+            tool_call_id: None,
+        }
+    }
+
+    /// Creates a new user message.
+    pub fn user(content: impl Into<String>) -> Self {
+        Self {
+            role: ChatRole::User,
+            content: content.into(),
+            tool_calls: Vec::new(),
+            tool_call_id: None,
+        }
+    }
+
+    /// Creates a new assistant message.
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Self {
+            role: ChatRole::Assistant,
+            content: content.into(),
+            tool_calls: Vec::new(),
+            tool_call_id: None,
+        }
+    }
+
+    /// Creates a new assistant message which requests tool calls (as returned in [`TextCompleteResponse::tool_calls`]).
+    pub fn assistant_tool_calls(content: impl Into<String>, tool_calls: Vec<ToolCall>) -> Self {
+        Self {
+            role: ChatRole::Assistant,
+            content: content.into(),
+            tool_calls,
+            tool_call_id: None,
+        }
+    }
+
+    /// Creates a new message with the result of a tool call.
+    pub fn tool_result(tool_call_id: impl Into<String>, content: impl Into<String>) -> Self {
+        Self {
+            role: ChatRole::Tool,
+            content: content.into(),
+            tool_calls: Vec::new(),
+            tool_call_id: Some(tool_call_id.into()),
+        }
+    }
+
+    /// Flattens a conversation into a system prompt (the system messages) and a prompt.
+    ///
+    /// A conversation with a single user message is returned as is, otherwise the messages are formatted
+    /// as a transcript (e.g., "User: Hello\n\nAssistant: Hi\n\nUser: How are you?").
+    pub fn transcript(messages: &[ChatMessage]) -> (String, String) {
+        let system_prompt = messages
+            .iter()
+            .filter(|message| message.role == ChatRole::System)
+            .map(|message| message.content.as_str())
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        let conversation = messages
+            .iter()
+            .filter(|message| message.role != ChatRole::System)
+            .collect::<Vec<_>>();
+        let prompt = match conversation.as_slice() {
+            [message] if message.role == ChatRole::User => message.content.clone(),
+            _ => conversation
+                .iter()
+                .map(|message| {
+                    let author = match message.role {
+                        ChatRole::System | ChatRole::User => "User",
+                        ChatRole::Assistant => "Assistant",
+                        ChatRole::Tool => "Tool",
+                    };
+                    format!("{author}: {}", message.content)
+                })
+                .collect::<Vec<_>>()
+                .join("\n\n"),
+        };
+        (system_prompt, prompt)
+    }
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct TextCompleteOptions {
     /// An encoding of the conversation used in this response, this can be sent in the next request to keep a conversational memory.
     /// This should be as returned from the previous response.
     pub context: Option<Vec<i64>>,
+    /// Sampling parameters for the generation.
+    pub generation: GenerationOptions,
+    /// Tools which the model may request to call.
+    pub tools: Vec<Tool>,
+    /// Format of the response (e.g., to request provider-level JSON mode).
+    pub response_format: ResponseFormat,
+}
+
+/// Format of the response of the model.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum ResponseFormat {
+    /// Free-form text.
+    #[default]
+    Text,
+    /// A JSON object (e.g., JSON mode of the provider).
+    Json,
+    /// A JSON object which conforms to the JSON Schema.
+    /// Providers which do not support schemas fall back to [`ResponseFormat::Json`].
+    JsonSchema(serde_json::Value),
 }
 
 #[derive(Debug, Clone, Default)]
 pub struct TextCompleteStreamOptions {
     pub context: Option<Vec<i64>>,
+    /// Sampling parameters for the generation.
+    pub generation: GenerationOptions,
+    /// Format of the response (e.g., JSON), for providers which support it.
+    pub response_format: ResponseFormat,
+}
+
+/// Sampling parameters for a generation, which each provider maps to its own request fields.
+/// Parameters which are not set use the defaults of the provider (or model).
+///
+/// Providers return [`LanguageModelError::UnsupportedFeature`] for parameters (or combinations of them) they do not support,
+/// and [`LanguageModelError::Configuration`] for values out of the range they accept.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GenerationOptions {
+    /// Amount of randomness injected into the response (e.g., 0.0 for analytical tasks, closer to 1.0 for creative tasks).
+    pub temperature: Option<f32>,
+    /// Maximum number of tokens to generate before stopping.
+    pub max_tokens: Option<usize>,
+    /// Nucleus sampling: only sample from the tokens comprising the top `top_p` probability mass.
+    pub top_p: Option<f32>,
+    /// Sequences which will cause the model to stop generating.
+    pub stop: Option<Vec<String>>,
+    /// Seed for (best effort) deterministic sampling.
+    pub seed: Option<u64>,
+}
+
+impl GenerationOptions {
+    /// Checks that the parameters are within the ranges accepted by the provider
+    /// (i.e., a temperature between 0 and `max_temperature`, if any, and a `top_p` between 0 and 1).
+    pub(crate) fn validate_ranges(
+        &self,
+        provider: &str,
+        max_temperature: Option<f32>,
+    ) -> Result<(), LanguageModelError> {
+        if let Some(temperature) = self.temperature {
+            let max = max_temperature.unwrap_or(f32::INFINITY);
+            if !(0.0..=max).contains(&temperature) {
+                let range = match max_temperature {
+                    Some(max_temperature) => format!("between 0 and {max_temperature}"),
+                    None => "at least 0".to_string(),
+                };
+                return Err(LanguageModelError::Configuration(format!(
+                    "The 'temperature' generation option must be {range} for {provider}, got {temperature}"
+                )));
+            }
+        }
+        if let Some(top_p) = self.top_p {
+            if !(0.0..=1.0).contains(&top_p) {
+                return Err(LanguageModelError::Configuration(format!(
+                    "The 'top_p' generation option must be between 0 and 1 for {provider}, got {top_p}"
+                )));
+            }
+        }
+        if self.max_tokens == Some(0) {
+            return Err(LanguageModelError::Configuration(
+                "The 'max_tokens' generation option must be positive".to_string(),
+            ));
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -85,10 +319,233 @@ pub struct TextCompleteResponse {
     pub text: String,
     // TODO: This is specific to Ollama, context looks differently for other LLM providers.
     pub context: Option<Vec<i64>>,
+    /// Token usage of the generation, if reported by the provider.
+    pub usage: Option<TokenUsage>,
+    /// The reason the model stopped generating, if reported by the provider.
+    pub finish_reason: Option<FinishReason>,
+    /// Tool calls requested by the model (empty if no tools were provided or the model answered directly).
+    pub tool_calls: Vec<ToolCall>,
+    /// The model which served the response (e.g., which model of a [`super::FallbackLanguageModel`]), if reported.
+    pub served_by: Option<ServingModel>,
+}
+
+/// A model which served a response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServingModel {
+    /// Provider of the model.
+    pub provider: LanguageModelProvider,
+    /// Name of the model used for text completions.
+    pub model: String,
+}
+
+impl ServingModel {
+    /// Returns the provider and text completion model of a language model.
+    pub fn of(lm: &dyn LanguageModel) -> Self {
+        Self {
+            provider: lm.provider(),
+            model: lm.text_completion_model_name(),
+        }
+    }
 }
 
 pub struct TextCompleteStreamResponse {
     pub stream: Pin<Box<dyn Stream<Item = Result<String, LanguageModelError>> + Send>>,
+    /// Metadata of the response, which is available once the stream has been fully consumed.
+    pub metadata: TextCompleteStreamMetadata,
     // TODO: Handle context with streaming response.
     // pub context: Vec<i64>,
 }
+
+impl TextCompleteStreamResponse {
+    /// Waits for the first item of the stream, so that an error when connecting is returned
+    /// before the stream is handed out (e.g., Ollama only connects once the stream is polled).
+    pub(crate) async fn started(mut self) -> Result<Self, LanguageModelError> {
+        match self.stream.next().await {
+            Some(Err(e)) => Err(e),
+            first => Ok(Self {
+                stream: Box::pin(tokio_stream::iter(first).chain(self.stream)),
+                metadata: self.metadata,
+            }),
+        }
+    }
+}
+
+/// Token usage of a single generation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TokenUsage {
+    /// Number of tokens in the prompt (including the system prompt and previous messages).
+    pub prompt_tokens: usize,
+    /// Number of tokens generated by the model.
+    pub completion_tokens: usize,
+}
+
+/// The reason a model stopped generating tokens.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FinishReason {
+    /// The model reached a natural stopping point (or a stop sequence).
+    Stop,
+    /// The maximum number of tokens was reached, so the output is truncated.
+    Length,
+    /// The output was omitted or truncated by a content filter of the provider.
+    ContentFilter,
+    /// The model requested to call one or more tools.
+    ToolCalls,
+    /// A provider-specific reason which is not covered by the other variants.
+    Other(String),
+}
+
+impl FinishReason {
+    /// Returns whether the output was cut short (e.g., by reaching the maximum number of tokens).
+    pub fn is_truncated(&self) -> bool {
+        matches!(self, FinishReason::Length | FinishReason::ContentFilter)
+    }
+}
+
+/// Metadata of a streaming response which is only known once the stream has finished.
+///
+/// The provider populates it while the stream is being consumed, so it is shared (cheaply cloned) with the stream.
+#[derive(Debug, Clone, Default)]
+pub struct TextCompleteStreamMetadata {
+    inner: Arc<Mutex<TextCompleteStreamMetadataInner>>,
+}
+
+#[derive(Debug, Default)]
+struct TextCompleteStreamMetadataInner {
+    usage: Option<TokenUsage>,
+    finish_reason: Option<FinishReason>,
+    served_by: Option<ServingModel>,
+    context: Option<Vec<i64>>,
+}
+
+impl TextCompleteStreamMetadata {
+    /// Returns the token usage of the generation, if reported by the provider and the stream has finished.
+    pub fn usage(&self) -> Option<TokenUsage> {
+        self.inner.lock().unwrap().usage
+    }
+
+    /// Returns the reason the model stopped generating, if reported by the provider and the stream has finished.
+    pub fn finish_reason(&self) -> Option<FinishReason> {
+        self.inner.lock().unwrap().finish_reason.clone()
+    }
+
+    /// Returns the model which serves the response, if reported.
+    pub fn served_by(&self) -> Option<ServingModel> {
+        self.inner.lock().unwrap().served_by.clone()
+    }
+
+    /// Returns the context of the conversation including this response (Ollama only), once the stream has finished.
+    pub fn context(&self) -> Option<Vec<i64>> {
+        self.inner.lock().unwrap().context.clone()
+    }
+
+    pub(crate) fn set_usage(&self, usage: TokenUsage) {
+        self.inner.lock().unwrap().usage = Some(usage);
+    }
+
+    pub(crate) fn set_finish_reason(&self, finish_reason: FinishReason) {
+        self.inner.lock().unwrap().finish_reason = Some(finish_reason);
+    }
+
+    pub(crate) fn set_served_by(&self, served_by: ServingModel) {
+        self.inner.lock().unwrap().served_by = Some(served_by);
+    }
+
+    pub(crate) fn set_context(&self, context: Vec<i64>) {
+        self.inner.lock().unwrap().context = Some(context);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A model which only implements text completion, and echoes the prompts.
+    #[derive(Clone)]
+    struct EchoLanguageModel;
+
+    #[async_trait]
+    impl LanguageModel for EchoLanguageModel {
+        async fn text_complete(
+            &self,
+            prompt: &str,
+            system_prompt: &str,
+            _options: TextCompleteOptions,
+        ) -> Result<TextCompleteResponse, LanguageModelError> {
+            Ok(TextCompleteResponse {
+                text: format!("{system_prompt}|{prompt}"),
+                context: None,
+                usage: None,
+                finish_reason: None,
+                tool_calls: Vec::new(),
+                served_by: None,
+            })
+        }
+
+        async fn text_complete_stream(
+            &self,
+            _prompt: &str,
+            _system_prompt: &str,
+            _options: TextCompleteStreamOptions,
+        ) -> Result<TextCompleteStreamResponse, LanguageModelError> {
+            Err(LanguageModelError::UnsupportedFeature(
+                "streaming".to_string(),
+            ))
+        }
+
+        async fn generate_embedding(&self, _prompt: &str) -> Result<Vec<f32>, LanguageModelError> {
+            Err(LanguageModelError::UnsupportedFeature(
+                "embeddings".to_string(),
+            ))
+        }
+
+        fn provider(&self) -> LanguageModelProvider {
+            LanguageModelProvider::Ollama
+        }
+
+        fn text_completion_model_name(&self) -> String {
+            "echo".to_string()
+        }
+
+        fn embedding_model_name(&self) -> String {
+            "echo".to_string()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_default_chat_complete() {
+        let response = EchoLanguageModel
+            .chat_complete(
+                &[
+                    ChatMessage::system("Be concise"),
+                    ChatMessage::user("Hello"),
+                    ChatMessage::assistant("Hi"),
+                    ChatMessage::user("How are you?"),
+                ],
+                TextCompleteOptions::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            response.text,
+            "Be concise|User: Hello\n\nAssistant: Hi\n\nUser: How are you?"
+        );
+    }
+
+    #[test]
+    fn test_transcript() {
+        let (system_prompt, prompt) = ChatMessage::transcript(&[
+            ChatMessage::system("Be concise"),
+            ChatMessage::user("Hello"),
+        ]);
+        assert_eq!(system_prompt, "Be concise");
+        assert_eq!(prompt, "Hello");
+
+        let (system_prompt, prompt) = ChatMessage::transcript(&[
+            ChatMessage::user("Hello"),
+            ChatMessage::assistant("Hi"),
+            ChatMessage::user("How are you?"),
+        ]);
+        assert_eq!(system_prompt, "");
+        assert_eq!(prompt, "User: Hello\n\nAssistant: Hi\n\nUser: How are you?");
+    }
+}
@@ -0,0 +1,348 @@
+use std::{
+    future::Future,
+    hash::{BuildHasher, Hasher},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+
+use super::{
+    ChatMessage, LanguageModel, LanguageModelBuilder, LanguageModelBuilderError,
+    LanguageModelError, LanguageModelProvider, TextCompleteOptions, TextCompleteResponse,
+    TextCompleteStreamOptions, TextCompleteStreamResponse,
+};
+
+/// The default maximum number of retries of a request, if not overriden.
+pub const DEFAULT_MAX_RETRIES: usize = 3;
+
+/// The default delay before the first retry, if not overriden.
+pub const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+/// The default maximum delay between retries, if not overriden.
+pub const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// The default maximum time to spend on a request (including retries), if not overriden.
+pub const DEFAULT_MAX_ELAPSED_TIME: Duration = Duration::from_secs(60);
+
+/// When and how long to wait before retrying a failed request (see [`RetryingLanguageModel`]).
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Maximum number of retries (i.e., not including the first attempt).
+    pub max_retries: usize,
+    /// Delay before the first retry, which is doubled for every subsequent retry.
+    pub initial_backoff: Duration,
+    /// Maximum delay between retries.
+    pub max_backoff: Duration,
+    /// Maximum time to spend on a request, after which it is not retried anymore.
+    pub max_elapsed_time: Option<Duration>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: DEFAULT_MAX_RETRIES,
+            initial_backoff: DEFAULT_INITIAL_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+            max_elapsed_time: Some(DEFAULT_MAX_ELAPSED_TIME),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns the delay before a retry (starting from 0), with a random jitter of up to half of the delay,
+    /// so that clients which failed at the same time do not retry at the same time.
+    pub fn backoff(&self, retry: usize) -> Duration {
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(retry.try_into().unwrap_or(u32::MAX)))
+            .min(self.max_backoff);
+        backoff / 2 + backoff.mul_f64(random_fraction() / 2.0)
+    }
+
+    /// Returns the delay before a retry after the error, or `None` if the request should not be retried.
+    ///
+    /// The delay requested by the provider (e.g., in a `retry-after` header) takes precedence over the backoff.
+    fn delay(
+        &self,
+        error: &LanguageModelError,
+        retry: usize,
+        started: Instant,
+    ) -> Option<Duration> {
+        if retry >= self.max_retries || !error.is_retryable() {
+            return None;
+        }
+        let delay = error.retry_after().unwrap_or_else(|| self.backoff(retry));
+        match self.max_elapsed_time {
+            Some(max_elapsed_time)
+                if started.elapsed().saturating_add(delay) > max_elapsed_time =>
+            {
+                None
+            }
+            _ => Some(delay),
+        }
+    }
+}
+
+/// Returns a random number in `[0, 1)`.
+fn random_fraction() -> f64 {
+    // The hasher is randomly seeded, which is random enough for a jitter.
+    let random = std::collections::hash_map::RandomState::new()
+        .build_hasher()
+        .finish();
+    (random >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// A language model which retries requests which fail with a transient error
+/// (e.g., a connection error, rate limit or overloaded server), with an exponential backoff.
+///
+/// Streaming requests are retried only if opening the stream (or its first item) fails.
+#[derive(Clone)]
+pub struct RetryingLanguageModel {
+    lm: Box<dyn LanguageModel>,
+    policy: RetryPolicy,
+}
+
+impl RetryingLanguageModel {
+    /// Returns the policy of the retries.
+    pub fn policy(&self) -> &RetryPolicy {
+        &self.policy
+    }
+
+    async fn retry<T, F, Fut>(&self, request: F) -> Result<T, LanguageModelError>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, LanguageModelError>>,
+    {
+        let started = Instant::now();
+        let mut retry = 0;
+        loop {
+            let error = match request().await {
+                Ok(response) => return Ok(response),
+                Err(e) => e,
+            };
+            let Some(delay) = self.policy.delay(&error, retry, started) else {
+                return Err(error);
+            };
+            tokio::time::sleep(delay).await;
+            retry += 1;
+        }
+    }
+}
+
+#[async_trait]
+impl LanguageModel for RetryingLanguageModel {
+    async fn text_complete(
+        &self,
+        prompt: &str,
+        system_prompt: &str,
+        options: TextCompleteOptions,
+    ) -> Result<TextCompleteResponse, LanguageModelError> {
+        self.retry(|| {
+            self.lm
+                .text_complete(prompt, system_prompt, options.clone())
+        })
+        .await
+    }
+
+    async fn text_complete_stream(
+        &self,
+        prompt: &str,
+        system_prompt: &str,
+        options: TextCompleteStreamOptions,
+    ) -> Result<TextCompleteStreamResponse, LanguageModelError> {
+        self.retry(|| async {
+            self.lm
+                .text_complete_stream(prompt, system_prompt, options.clone())
+                .await?
+                .started()
+                .await
+        })
+        .await
+    }
+
+    async fn generate_embedding(&self, prompt: &str) -> Result<Vec<f32>, LanguageModelError> {
+        self.retry(|| self.lm.generate_embedding(prompt)).await
+    }
+
+    async fn chat_complete(
+        &self,
+        messages: &[ChatMessage],
+        options: TextCompleteOptions,
+    ) -> Result<TextCompleteResponse, LanguageModelError> {
+        self.retry(|| self.lm.chat_complete(messages, options.clone()))
+            .await
+    }
+
+    fn provider(&self) -> LanguageModelProvider {
+        self.lm.provider()
+    }
+
+    fn text_completion_model_name(&self) -> String {
+        self.lm.text_completion_model_name()
+    }
+
+    fn embedding_model_name(&self) -> String {
+        self.lm.embedding_model_name()
+    }
+}
+
+/// Builds a [`RetryingLanguageModel`] instance.
+pub struct RetryingLanguageModelBuilder {
+    lm: Option<Box<dyn LanguageModel>>,
+    policy: RetryPolicy,
+}
+
+impl RetryingLanguageModelBuilder {
+    /// Sets the language model whose requests are retried.
+    pub fn with_lm(mut self, lm: Box<dyn LanguageModel>) -> Self {
+        self.lm = Some(lm);
+        self
+    }
+
+    /// Sets the maximum number of retries. Defaults to [`DEFAULT_MAX_RETRIES`].
+    pub fn with_max_retries(mut self, max_retries: usize) -> Self {
+        self.policy.max_retries = max_retries;
+        self
+    }
+
+    /// Sets the delay before the first retry. Defaults to [`DEFAULT_INITIAL_BACKOFF`].
+    pub fn with_initial_backoff(mut self, initial_backoff: Duration) -> Self {
+        self.policy.initial_backoff = initial_backoff;
+        self
+    }
+
+    /// Sets the maximum delay between retries. Defaults to [`DEFAULT_MAX_BACKOFF`].
+    pub fn with_max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.policy.max_backoff = max_backoff;
+        self
+    }
+
+    /// Sets the maximum time to spend on a request, including retries. Defaults to [`DEFAULT_MAX_ELAPSED_TIME`].
+    pub fn with_max_elapsed_time(mut self, max_elapsed_time: Option<Duration>) -> Self {
+        self.policy.max_elapsed_time = max_elapsed_time;
+        self
+    }
+
+    /// Sets the whole retry policy at once.
+    pub fn with_policy(mut self, policy: RetryPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+}
+
+impl LanguageModelBuilder<RetryingLanguageModel> for RetryingLanguageModelBuilder {
+    fn new() -> Self {
+        Self {
+            lm: None,
+            policy: RetryPolicy::default(),
+        }
+    }
+
+    /// Tries to build a [`RetryingLanguageModel`] instance. Fails if the language model is not set.
+    fn try_build(self) -> Result<RetryingLanguageModel, LanguageModelBuilderError> {
+        let Some(lm) = self.lm else {
+            return Err(LanguageModelBuilderError::ConfigurationNotSet(
+                "Language model".to_string(),
+            ));
+        };
+        Ok(RetryingLanguageModel {
+            lm,
+            policy: self.policy,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        lm::{ApiErrorDetails, OpenAiError},
+        test_utils::{FailingLanguageModel, ScriptedLanguageModel},
+    };
+
+    use super::*;
+
+    fn rate_limited() -> LanguageModelError {
+        LanguageModelError::OpenAi(OpenAiError::Http(Box::new(ApiErrorDetails {
+            status: 429,
+            message: "Rate limit reached".to_string(),
+            retry_after: Some(Duration::from_millis(1)),
+            ..Default::default()
+        })))
+    }
+
+    fn unauthorized() -> LanguageModelError {
+        LanguageModelError::OpenAi(OpenAiError::Http(Box::new(ApiErrorDetails {
+            status: 401,
+            message: "Invalid API key".to_string(),
+            ..Default::default()
+        })))
+    }
+
+    #[tokio::test]
+    async fn test_retry() {
+        let lm = RetryingLanguageModelBuilder::new()
+            .with_lm(Box::new(
+                FailingLanguageModel::new(rate_limited)
+                    .with_failures(2, ScriptedLanguageModel::new(&["Paris"])),
+            ))
+            .with_initial_backoff(Duration::from_millis(1))
+            .try_build()
+            .unwrap();
+
+        let response = lm
+            .text_complete("Capital of France?", "", TextCompleteOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(response.text, "Paris");
+    }
+
+    #[tokio::test]
+    async fn test_retry_not_retryable() {
+        let failing = FailingLanguageModel::new(unauthorized)
+            .with_failures(1, ScriptedLanguageModel::new(&["Paris"]));
+        let lm = RetryingLanguageModelBuilder::new()
+            .with_lm(Box::new(failing))
+            .with_initial_backoff(Duration::from_millis(1))
+            .try_build()
+            .unwrap();
+
+        let result = lm
+            .text_complete("Capital of France?", "", TextCompleteOptions::default())
+            .await;
+        let Err(error) = result else {
+            panic!("Expected the request to fail");
+        };
+        assert_eq!(
+            error.api_error_details().map(|details| details.status),
+            Some(401)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_retry_exhausted() {
+        let lm = RetryingLanguageModelBuilder::new()
+            .with_lm(Box::new(FailingLanguageModel::new(rate_limited)))
+            .with_max_retries(2)
+            .try_build()
+            .unwrap();
+
+        let result = lm
+            .text_complete_stream("Capital?", "", TextCompleteStreamOptions::default())
+            .await;
+        assert!(result.is_err_and(|e| e.is_retryable()));
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(5),
+            ..Default::default()
+        };
+        for retry in 0..10 {
+            let expected = Duration::from_secs(2u64.pow(retry as u32).min(5));
+            let backoff = policy.backoff(retry);
+            assert!(backoff >= expected / 2 && backoff <= expected);
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use serde::{Deserialize, Serialize};
+
+/// A tool (function) which can be exposed to a language model.
+/// The model may then respond with [`ToolCall`]s instead of (or in addition to) text.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tool {
+    /// Name of the tool (e.g., "get_weather"), which the model uses to call it.
+    pub name: String,
+    /// Description of what the tool does and when it should be used.
+    pub description: String,
+    /// JSON Schema of the parameters of the tool (should be an object schema).
+    pub parameters: serde_json::Value,
+}
+
+impl Tool {
+    /// Creates a new tool.
+    ///
+    /// # Arguments
+    /// * `name` - Name of the tool.
+    /// * `description` - Description of what the tool does.
+    /// * `parameters` - JSON Schema of the parameters (e.g., `{"type": "object", "properties": {...}}`).
+    pub fn new(
+        name: impl Into<String>,
+        description: impl Into<String>,
+        parameters: serde_json::Value,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            parameters,
+        }
+    }
+}
+
+/// A request from the language model to call a [`Tool`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToolCall {
+    /// Identifier of the tool call, used to associate the result with the call.
+    pub id: String,
+    /// Name of the tool to call.
+    pub name: String,
+    /// Arguments for the tool, which should conform to the parameters schema of the tool.
+    /// If the model produced arguments which are not valid JSON, this is a JSON string with the raw arguments.
+    pub arguments: serde_json::Value,
+}
@@ -0,0 +1,56 @@
+use std::time::Duration;
+
+use reqwest::header::HeaderMap;
+
+/// Returns the time to wait before retrying a request, as requested by the server in the
+/// `retry-after-ms` (OpenAI) or `retry-after` headers.
+///
+/// Only delays in seconds are supported for `retry-after` (HTTP dates are ignored).
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<f64>().ok())
+    };
+    // Invalid (e.g., negative or too large) delays are ignored, rather than panicking.
+    header("retry-after-ms")
+        .and_then(|ms| Duration::try_from_secs_f64(ms / 1000.0).ok())
+        .or_else(|| header("retry-after").and_then(|secs| Duration::try_from_secs_f64(secs).ok()))
+}
+
+/// Returns the value of a header, if it is set (and valid UTF-8).
+pub fn header_value(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(ToString::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_after() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers), None);
+        headers.insert("retry-after", "2".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(2)));
+        headers.insert("retry-after-ms", "150".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_millis(150)));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "retry-after",
+            "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap(),
+        );
+        assert_eq!(retry_after(&headers), None);
+
+        let mut headers = HeaderMap::new();
+        headers.insert("retry-after", "1e30".parse().unwrap());
+        assert_eq!(retry_after(&headers), None);
+        headers.insert("retry-after-ms", "-5".parse().unwrap());
+        assert_eq!(retry_after(&headers), None);
+    }
+}
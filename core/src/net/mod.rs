@@ -1,4 +1,7 @@
+/// Module for working with HTTP responses.
+mod http;
 /// Module for working with Server-Sent Events.
 mod sse;
 
+pub use http::*;
 pub use sse::*;
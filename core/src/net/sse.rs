@@ -1,28 +1,309 @@
 use async_gen::AsyncIter;
-use reqwest::{header, Client};
+use reqwest::{header, header::HeaderMap, Client};
+use thiserror::Error;
 use tokio_stream::Stream;
 
+#[derive(Debug, Error)]
+pub enum NetError {
+    #[error("Failed to send request: {0}")]
+    Request(String),
+
+    #[error("Connection error while receiving the response: {0}")]
+    Connection(String),
+
+    #[error("Unexpected HTTP status {status}: {body}")]
+    Status {
+        status: u16,
+        /// Headers of the response (e.g., `retry-after`).
+        headers: HeaderMap,
+        body: String,
+    },
+}
+
+/// The format of a streamed response body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventStreamFormat {
+    /// Server-Sent Events (`text/event-stream`), as specified [here](https://html.spec.whatwg.org/multipage/server-sent-events.html#event-stream-interpretation).
+    EventStream,
+    /// Newline-delimited JSON (`application/x-ndjson`), where every non-empty line is an event.
+    NewlineDelimitedJson,
+}
+
+/// An event received from a streamed response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    /// The event type (defaults to "message").
+    pub event: String,
+    /// The data of the event. Multiple `data` fields are joined with a newline.
+    pub data: String,
+    /// The last event ID received in the stream, if any.
+    pub id: Option<String>,
+    /// The reconnection time (in milliseconds) requested by the server, if any.
+    pub retry: Option<u64>,
+}
+
 /// A client for working with Server-Sent Events.
 pub struct SseClient;
 
 impl SseClient {
-    pub fn post(url: &str, body: Option<String>) -> impl Stream<Item = String> {
+    pub fn post(
+        url: &str,
+        format: EventStreamFormat,
+        headers: HeaderMap,
+        body: Option<String>,
+    ) -> impl Stream<Item = Result<Event, NetError>> {
         let client = Client::new();
         let mut req = Client::post(&client, url)
             .header(header::ACCEPT, "text/event-stream")
             .header(header::CACHE_CONTROL, "no-cache")
             .header(header::CONNECTION, "keep-alive")
-            .header(header::CONTENT_TYPE, "application/json");
+            .header(header::CONTENT_TYPE, "application/json")
+            .headers(headers);
         if let Some(body) = body {
             req = req.body(body);
         }
-        let req = req.build().unwrap();
+        let req = req.build();
 
         AsyncIter::from(async_gen::gen! {
-            let mut conn = client.execute(req).await.unwrap();
-            while let Some(event) = conn.chunk().await.unwrap() {
-                yield std::str::from_utf8(&event).unwrap().to_owned();
+            let req = match req {
+                Ok(req) => req,
+                Err(e) => {
+                    yield Err(NetError::Request(e.to_string()));
+                    return;
+                }
+            };
+            let mut conn = match client.execute(req).await {
+                Ok(conn) => conn,
+                Err(e) => {
+                    yield Err(NetError::Request(e.to_string()));
+                    return;
+                }
+            };
+            let status = conn.status();
+            if !status.is_success() {
+                let headers = conn.headers().clone();
+                let body = conn.text().await.unwrap_or_default();
+                yield Err(NetError::Status { status: status.as_u16(), headers, body });
+                return;
+            }
+
+            let mut parser = EventParser::new(format);
+            loop {
+                match conn.chunk().await {
+                    Ok(Some(chunk)) => {
+                        for event in parser.feed(&chunk) {
+                            yield Ok(event);
+                        }
+                    }
+                    Ok(None) => break,
+                    Err(e) => {
+                        yield Err(NetError::Connection(e.to_string()));
+                        return;
+                    }
+                }
+            }
+            for event in parser.finish() {
+                yield Ok(event);
+            }
+        })
+    }
+}
+
+/// An incremental parser for streamed response bodies.
+///
+/// Chunks received from the network are not guaranteed to align with line (or even UTF-8 character) boundaries,
+/// so bytes are buffered until a complete line has been received.
+pub(crate) struct EventParser {
+    format: EventStreamFormat,
+    buffer: Vec<u8>,
+    is_first_line: bool,
+    event: Option<String>,
+    data: Option<String>,
+    last_event_id: Option<String>,
+    retry: Option<u64>,
+}
+
+impl EventParser {
+    pub(crate) fn new(format: EventStreamFormat) -> Self {
+        Self {
+            format,
+            buffer: Vec::new(),
+            is_first_line: true,
+            event: None,
+            data: None,
+            last_event_id: None,
+            retry: None,
+        }
+    }
+
+    /// Feeds a chunk of the response body and returns the events which were completed by it.
+    pub(crate) fn feed(&mut self, chunk: &[u8]) -> Vec<Event> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut events = Vec::new();
+        while let Some((line_end, terminator_len)) = self.next_line_end() {
+            let line = self
+                .buffer
+                .drain(..line_end + terminator_len)
+                .collect::<Vec<_>>();
+            let line = String::from_utf8_lossy(&line[..line_end]).into_owned();
+            if let Some(event) = self.process_line(line) {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    /// Signals the end of the response body and returns the remaining events.
+    ///
+    /// Per the specification, an incomplete server-sent event (not followed by an empty line) is discarded,
+    /// whereas a trailing line of newline-delimited JSON does not require a line terminator.
+    pub(crate) fn finish(&mut self) -> Vec<Event> {
+        let remaining = std::mem::take(&mut self.buffer);
+        let remaining = String::from_utf8_lossy(&remaining).into_owned();
+        match self.format {
+            EventStreamFormat::EventStream => Vec::new(),
+            EventStreamFormat::NewlineDelimitedJson => {
+                self.process_line(remaining).into_iter().collect()
+            }
+        }
+    }
+
+    /// Finds the end of the next complete line in the buffer, returning its length and the length of its terminator.
+    /// Lines may be terminated by "\r\n", "\n" or "\r".
+    fn next_line_end(&self) -> Option<(usize, usize)> {
+        let position = self
+            .buffer
+            .iter()
+            .position(|b| *b == b'\n' || *b == b'\r')?;
+        if self.buffer[position] == b'\n' {
+            return Some((position, 1));
+        }
+        match self.buffer.get(position + 1) {
+            Some(b'\n') => Some((position, 2)),
+            Some(_) => Some((position, 1)),
+            // A trailing "\r" may be followed by "\n" in the next chunk.
+            None => None,
+        }
+    }
+
+    fn process_line(&mut self, mut line: String) -> Option<Event> {
+        if self.is_first_line {
+            self.is_first_line = false;
+            if let Some(stripped) = line.strip_prefix('\u{feff}') {
+                line = stripped.to_owned();
+            }
+        }
+
+        match self.format {
+            EventStreamFormat::NewlineDelimitedJson => {
+                let line = line.trim();
+                if line.is_empty() {
+                    return None;
+                }
+                Some(Event {
+                    event: "message".to_owned(),
+                    data: line.to_owned(),
+                    id: None,
+                    retry: None,
+                })
+            }
+            EventStreamFormat::EventStream => {
+                if line.is_empty() {
+                    return self.dispatch();
+                }
+                if line.starts_with(':') {
+                    // Comment (e.g., used as a keep-alive).
+                    return None;
+                }
+                let (field, value) = match line.split_once(':') {
+                    Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+                    None => (line.as_str(), ""),
+                };
+                match field {
+                    "event" => self.event = Some(value.to_owned()),
+                    "data" => match &mut self.data {
+                        Some(data) => {
+                            data.push('\n');
+                            data.push_str(value);
+                        }
+                        None => self.data = Some(value.to_owned()),
+                    },
+                    "id" if !value.contains('\0') => self.last_event_id = Some(value.to_owned()),
+                    "retry" => {
+                        if let Ok(retry) = value.parse::<u64>() {
+                            self.retry = Some(retry);
+                        }
+                    }
+                    // Unknown fields are ignored.
+                    _ => {}
+                }
+                None
             }
+        }
+    }
+
+    fn dispatch(&mut self) -> Option<Event> {
+        let event = self.event.take();
+        let data = self.data.take()?;
+        Some(Event {
+            event: event
+                .filter(|event| !event.is_empty())
+                .unwrap_or("message".to_owned()),
+            data,
+            id: self.last_event_id.clone(),
+            retry: self.retry,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_stream_split_across_chunks() {
+        let mut parser = EventParser::new(EventStreamFormat::EventStream);
+        assert!(parser.feed(b"event: message_start\nda").is_empty());
+        assert!(parser.feed(b"ta: {\"a\": 1}\r").is_empty());
+        let events = parser.feed(b"\n\r\ndata: second\n\n");
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].event, "message_start");
+        assert_eq!(events[0].data, "{\"a\": 1}");
+        assert_eq!(events[1].event, "message");
+        assert_eq!(events[1].data, "second");
+    }
+
+    #[test]
+    fn test_event_stream_fields() {
+        let mut parser = EventParser::new(EventStreamFormat::EventStream);
+        let events = parser.feed(
+            b": keep-alive\n\nid: 42\nretry: 3000\ndata: line 1\ndata:line 2\n\ndata: next\n\n",
+        );
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].data, "line 1\nline 2");
+        assert_eq!(events[0].id.as_deref(), Some("42"));
+        assert_eq!(events[0].retry, Some(3000));
+        // The last event ID persists across events.
+        assert_eq!(events[1].id.as_deref(), Some("42"));
+
+        // An incomplete event at the end of the stream is discarded.
+        assert!(parser.feed(b"data: incomplete").is_empty());
+        assert!(parser.finish().is_empty());
+    }
+
+    #[test]
+    fn test_newline_delimited_json() {
+        let mut parser = EventParser::new(EventStreamFormat::NewlineDelimitedJson);
+        let bytes = "{\"é\": 1}\n{\"b\"".as_bytes();
+        // Split in the middle of the two-byte "é".
+        assert!(parser.feed(&bytes[..3]).is_empty());
+        let events = parser.feed(&bytes[3..]);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].data, "{\"é\": 1}");
+        assert!(parser.feed(b": 2}").is_empty());
+        let events = parser.finish();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].data, "{\"b\": 2}");
+    }
+}
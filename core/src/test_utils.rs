@@ -0,0 +1,213 @@
+//! Utilities for unit tests.
+
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+
+use crate::lm::{
+    ChatMessage, GenerationOptions, LanguageModel, LanguageModelError, LanguageModelProvider,
+    TextCompleteOptions, TextCompleteResponse, TextCompleteStreamMetadata,
+    TextCompleteStreamOptions, TextCompleteStreamResponse, TokenUsage, ToolCall,
+};
+
+/// A language model which replies with pre-defined responses, recording the conversations
+/// (and the generation options) it received.
+#[derive(Clone, Default)]
+pub(crate) struct ScriptedLanguageModel {
+    pub(crate) responses: Arc<Mutex<Vec<TextCompleteResponse>>>,
+    pub(crate) conversations: Arc<Mutex<Vec<Vec<ChatMessage>>>>,
+    pub(crate) generations: Arc<Mutex<Vec<GenerationOptions>>>,
+}
+
+impl ScriptedLanguageModel {
+    /// Creates a model which replies with the texts, in order.
+    pub(crate) fn new(texts: &[&str]) -> Self {
+        let lm = Self::default();
+        *lm.responses.lock().unwrap() = texts
+            .iter()
+            .map(|text| scripted_response(text, Vec::new()))
+            .collect();
+        lm
+    }
+}
+
+#[async_trait]
+impl LanguageModel for ScriptedLanguageModel {
+    async fn text_complete(
+        &self,
+        prompt: &str,
+        system_prompt: &str,
+        options: TextCompleteOptions,
+    ) -> Result<TextCompleteResponse, LanguageModelError> {
+        let messages = [
+            ChatMessage::system(system_prompt),
+            ChatMessage::user(prompt),
+        ];
+        self.chat_complete(&messages, options).await
+    }
+
+    /// Streams the next response in chunks of a few characters.
+    async fn text_complete_stream(
+        &self,
+        prompt: &str,
+        system_prompt: &str,
+        _options: TextCompleteStreamOptions,
+    ) -> Result<TextCompleteStreamResponse, LanguageModelError> {
+        let messages = [
+            ChatMessage::system(system_prompt),
+            ChatMessage::user(prompt),
+        ];
+        let response = self
+            .chat_complete(&messages, TextCompleteOptions::default())
+            .await?;
+        let chars = response.text.chars().collect::<Vec<_>>();
+        let chunks = chars
+            .chunks(4)
+            .map(|chunk| Ok(chunk.iter().collect::<String>()))
+            .collect::<Vec<_>>();
+        Ok(TextCompleteStreamResponse {
+            stream: Box::pin(tokio_stream::iter(chunks)),
+            metadata: TextCompleteStreamMetadata::default(),
+        })
+    }
+
+    async fn generate_embedding(&self, _prompt: &str) -> Result<Vec<f32>, LanguageModelError> {
+        Err(LanguageModelError::UnsupportedFeature(
+            "embeddings".to_string(),
+        ))
+    }
+
+    async fn chat_complete(
+        &self,
+        messages: &[ChatMessage],
+        options: TextCompleteOptions,
+    ) -> Result<TextCompleteResponse, LanguageModelError> {
+        self.conversations.lock().unwrap().push(messages.to_vec());
+        self.generations.lock().unwrap().push(options.generation);
+        Ok(self.responses.lock().unwrap().remove(0))
+    }
+
+    fn provider(&self) -> LanguageModelProvider {
+        LanguageModelProvider::Ollama
+    }
+
+    fn text_completion_model_name(&self) -> String {
+        "scripted".to_string()
+    }
+
+    fn embedding_model_name(&self) -> String {
+        "scripted".to_string()
+    }
+}
+
+/// A language model which fails requests with an error (e.g., a provider which is not running),
+/// and then (optionally) replies like a [`ScriptedLanguageModel`].
+///
+/// Like Ollama, its streams fail when they are first polled.
+#[derive(Clone)]
+pub(crate) struct FailingLanguageModel {
+    error: fn() -> LanguageModelError,
+    failures: Arc<Mutex<usize>>,
+    then: Option<ScriptedLanguageModel>,
+}
+
+impl FailingLanguageModel {
+    /// Creates a model which fails every request.
+    pub(crate) fn new(error: fn() -> LanguageModelError) -> Self {
+        Self {
+            error,
+            failures: Arc::new(Mutex::new(usize::MAX)),
+            then: None,
+        }
+    }
+
+    /// Fails only the first `failures` requests, and replies with `then` afterwards.
+    pub(crate) fn with_failures(mut self, failures: usize, then: ScriptedLanguageModel) -> Self {
+        self.failures = Arc::new(Mutex::new(failures));
+        self.then = Some(then);
+        self
+    }
+
+    /// Returns the model to reply with, or the error if the request should fail.
+    fn next(&self) -> Result<&ScriptedLanguageModel, LanguageModelError> {
+        let mut failures = self.failures.lock().unwrap();
+        match &self.then {
+            Some(then) if *failures == 0 => Ok(then),
+            _ => {
+                *failures = failures.saturating_sub(1);
+                Err((self.error)())
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl LanguageModel for FailingLanguageModel {
+    async fn text_complete(
+        &self,
+        prompt: &str,
+        system_prompt: &str,
+        options: TextCompleteOptions,
+    ) -> Result<TextCompleteResponse, LanguageModelError> {
+        self.next()?
+            .text_complete(prompt, system_prompt, options)
+            .await
+    }
+
+    async fn text_complete_stream(
+        &self,
+        prompt: &str,
+        system_prompt: &str,
+        options: TextCompleteStreamOptions,
+    ) -> Result<TextCompleteStreamResponse, LanguageModelError> {
+        match self.next() {
+            Ok(then) => {
+                then.text_complete_stream(prompt, system_prompt, options)
+                    .await
+            }
+            Err(e) => Ok(TextCompleteStreamResponse {
+                stream: Box::pin(tokio_stream::iter([Err(e)])),
+                metadata: TextCompleteStreamMetadata::default(),
+            }),
+        }
+    }
+
+    async fn generate_embedding(&self, prompt: &str) -> Result<Vec<f32>, LanguageModelError> {
+        self.next()?.generate_embedding(prompt).await
+    }
+
+    async fn chat_complete(
+        &self,
+        messages: &[ChatMessage],
+        options: TextCompleteOptions,
+    ) -> Result<TextCompleteResponse, LanguageModelError> {
+        self.next()?.chat_complete(messages, options).await
+    }
+
+    fn provider(&self) -> LanguageModelProvider {
+        LanguageModelProvider::Ollama
+    }
+
+    fn text_completion_model_name(&self) -> String {
+        "failing".to_string()
+    }
+
+    fn embedding_model_name(&self) -> String {
+        "failing".to_string()
+    }
+}
+
+/// A response of a [`ScriptedLanguageModel`], which reports a fixed token usage.
+pub(crate) fn scripted_response(text: &str, tool_calls: Vec<ToolCall>) -> TextCompleteResponse {
+    TextCompleteResponse {
+        text: text.to_string(),
+        context: None,
+        usage: Some(TokenUsage {
+            prompt_tokens: 10,
+            completion_tokens: 5,
+        }),
+        finish_reason: None,
+        tool_calls,
+        served_by: None,
+    }
+}
@@ -0,0 +1,272 @@
+use serde_json::{json, Map, Value};
+
+/// The JSON Schema dialect of the generated schemas.
+pub const JSON_SCHEMA_DIALECT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+/// Name of the field which discriminates between the variants of a response.
+pub const RESPONSE_TYPE_FIELD: &str = "response_type";
+
+/// Converts the type of a [`crate::ResponseSchemaField`] (e.g., "string", "string?" or "string[]") to a JSON Schema.
+///
+/// A `?` suffix denotes a nullable type, and a `[]` suffix denotes an array of the preceding type.
+pub fn field_type_json_schema(typ: &str) -> Value {
+    if let Some(typ) = typ.strip_suffix('?') {
+        let mut schema = field_type_json_schema(typ);
+        match schema.get_mut("type") {
+            Some(Value::String(inner)) => {
+                let inner = inner.clone();
+                schema["type"] = json!([inner, "null"]);
+                schema
+            }
+            _ => json!({ "anyOf": [schema, { "type": "null" }] }),
+        }
+    } else if let Some(typ) = typ.strip_suffix("[]") {
+        json!({ "type": "array", "items": field_type_json_schema(typ) })
+    } else {
+        json!({ "type": typ })
+    }
+}
+
+/// An error found when validating a value against a JSON Schema.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaValidationError {
+    /// JSON pointer to the invalid value (e.g., "/capital").
+    pub path: String,
+    /// Description of the error.
+    pub message: String,
+}
+
+impl std::fmt::Display for SchemaValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let path = if self.path.is_empty() {
+            "/"
+        } else {
+            &self.path
+        };
+        write!(f, "{}: {}", path, self.message)
+    }
+}
+
+/// Validates a value against a JSON Schema.
+///
+/// Only the keywords used in the generated schemas are supported (`type`, `const`, `enum`, `properties`,
+/// `required`, `additionalProperties`, `items`, `oneOf` and `anyOf`), other keywords are ignored.
+pub fn validate_json_schema(
+    schema: &Value,
+    value: &Value,
+) -> Result<(), Vec<SchemaValidationError>> {
+    let mut errors = Vec::new();
+    validate(schema, value, "", &mut errors);
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+fn validate(schema: &Value, value: &Value, path: &str, errors: &mut Vec<SchemaValidationError>) {
+    let Some(schema) = schema.as_object() else {
+        // Boolean schemas.
+        if schema == &Value::Bool(false) {
+            push_error(errors, path, "No value is allowed".to_string());
+        }
+        return;
+    };
+
+    if let Some(typ) = schema.get("type") {
+        let types = match typ {
+            Value::Array(types) => types.iter().filter_map(Value::as_str).collect::<Vec<_>>(),
+            typ => typ.as_str().into_iter().collect(),
+        };
+        if !types.iter().any(|typ| matches_type(typ, value)) {
+            push_error(
+                errors,
+                path,
+                format!(
+                    "Expected type {}, found {}",
+                    types.join(" or "),
+                    type_name(value)
+                ),
+            );
+            return;
+        }
+    }
+    if let Some(expected) = schema.get("const") {
+        if expected != value {
+            push_error(errors, path, format!("Expected {expected}, found {value}"));
+        }
+    }
+    if let Some(Value::Array(allowed)) = schema.get("enum") {
+        if !allowed.contains(value) {
+            push_error(
+                errors,
+                path,
+                format!("{value} is not one of the allowed values"),
+            );
+        }
+    }
+    if let Value::Object(object) = value {
+        validate_object(schema, object, path, errors);
+    }
+    if let (Value::Array(items), Some(items_schema)) = (value, schema.get("items")) {
+        for (i, item) in items.iter().enumerate() {
+            validate(items_schema, item, &format!("{path}/{i}"), errors);
+        }
+    }
+    if let Some(Value::Array(schemas)) = schema.get("oneOf") {
+        let matches = schemas
+            .iter()
+            .filter(|schema| validate_json_schema(schema, value).is_ok())
+            .count();
+        if matches != 1 {
+            push_error(
+                errors,
+                path,
+                format!("Expected exactly one matching schema, found {matches}"),
+            );
+        }
+    }
+    if let Some(Value::Array(schemas)) = schema.get("anyOf") {
+        if !schemas
+            .iter()
+            .any(|schema| validate_json_schema(schema, value).is_ok())
+        {
+            push_error(errors, path, "No matching schema was found".to_string());
+        }
+    }
+}
+
+fn validate_object(
+    schema: &Map<String, Value>,
+    object: &Map<String, Value>,
+    path: &str,
+    errors: &mut Vec<SchemaValidationError>,
+) {
+    let properties = schema.get("properties").and_then(Value::as_object);
+    if let Some(Value::Array(required)) = schema.get("required") {
+        for name in required.iter().filter_map(Value::as_str) {
+            if !object.contains_key(name) {
+                push_error(errors, path, format!("Missing required property '{name}'"));
+            }
+        }
+    }
+    for (name, property_value) in object {
+        let property_path = format!("{path}/{name}");
+        match properties.and_then(|properties| properties.get(name)) {
+            Some(property_schema) => {
+                validate(property_schema, property_value, &property_path, errors)
+            }
+            None => match schema.get("additionalProperties") {
+                Some(Value::Bool(false)) => {
+                    push_error(errors, path, format!("Unexpected property '{name}'"))
+                }
+                Some(additional_schema) => {
+                    validate(additional_schema, property_value, &property_path, errors)
+                }
+                None => {}
+            },
+        }
+    }
+}
+
+fn matches_type(typ: &str, value: &Value) -> bool {
+    match typ {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => {
+            value.is_i64() || value.is_u64() || value.as_f64().is_some_and(|n| n.fract() == 0.0)
+        }
+        _ => false,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn push_error(errors: &mut Vec<SchemaValidationError>, path: &str, message: String) {
+    errors.push(SchemaValidationError {
+        path: path.to_string(),
+        message,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_field_type_json_schema() {
+        assert_eq!(
+            field_type_json_schema("string"),
+            json!({ "type": "string" })
+        );
+        assert_eq!(
+            field_type_json_schema("boolean?"),
+            json!({ "type": ["boolean", "null"] })
+        );
+        assert_eq!(
+            field_type_json_schema("string[]"),
+            json!({ "type": "array", "items": { "type": "string" } })
+        );
+    }
+
+    #[test]
+    fn test_validate_json_schema() {
+        let schema = json!({
+            "oneOf": [
+                {
+                    "type": "object",
+                    "properties": {
+                        "response_type": { "const": "Answer" },
+                        "capital": { "type": "string" }
+                    },
+                    "required": ["response_type", "capital"],
+                    "additionalProperties": false
+                },
+                {
+                    "type": "object",
+                    "properties": {
+                        "response_type": { "const": "Fail" },
+                        "reason": { "type": ["string", "null"] }
+                    },
+                    "required": ["response_type", "reason"],
+                    "additionalProperties": false
+                }
+            ]
+        });
+        assert!(validate_json_schema(
+            &schema,
+            &json!({ "response_type": "Answer", "capital": "London" })
+        )
+        .is_ok());
+        assert!(
+            validate_json_schema(&schema, &json!({ "response_type": "Fail", "reason": null }))
+                .is_ok()
+        );
+
+        let errors = validate_json_schema(
+            &schema["oneOf"][0],
+            &json!({ "response_type": "Answer", "capital": 1, "extra": true }),
+        )
+        .unwrap_err();
+        assert_eq!(
+            errors.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            vec![
+                "/capital: Expected type string, found number",
+                "/: Unexpected property 'extra'"
+            ]
+        );
+    }
+}
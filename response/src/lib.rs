@@ -1,5 +1,21 @@
 use dyn_clone::DynClone;
 
+mod json_schema;
+mod partial;
+mod repair;
+mod schema;
+
+pub use json_schema::*;
+pub use partial::*;
+pub use repair::*;
+pub use schema::*;
+
+// Used by the code generated by `orch_response_derive`.
+#[doc(hidden)]
+pub use serde;
+#[doc(hidden)]
+pub use serde_json;
+
 /// Represents an option for the response of a language model.
 #[derive(Debug, Clone)]
 pub struct ResponseOption {
@@ -20,10 +36,33 @@ pub struct ResponseSchemaField {
     pub name: String,
     /// Description of the field (e.g., "Capital city of the received country").
     pub description: String,
-    /// Type of the field (e.g., "string" for a string).
+    /// Type of the field (e.g., "string" for a string), as described to the model.
     pub typ: String,
     /// Example of the field (e.g., "London" for the capital city).
     pub example: String,
+    /// JSON Schema of the field (without the description). If `null`, it is derived from `typ`.
+    pub schema: serde_json::Value,
+    /// Whether the field may be omitted from the response (e.g., a field with `#[serde(default)]`).
+    pub optional: bool,
+}
+
+impl ResponseOption {
+    /// Returns a JSON Schema (draft 2020-12) of the response, including the `response_type` discriminator field.
+    ///
+    /// All fields which are not [`ResponseSchemaField::optional`] are required, and no additional fields are allowed.
+    pub fn json_schema(&self) -> serde_json::Value {
+        let mut schema = object_json_schema(&self.schema);
+        schema["properties"][RESPONSE_TYPE_FIELD] =
+            serde_json::json!({ "type": "string", "const": self.type_name });
+        if let Some(required) = schema["required"].as_array_mut() {
+            required.push(serde_json::Value::String(RESPONSE_TYPE_FIELD.to_string()));
+        }
+        schema["$schema"] = serde_json::Value::String(JSON_SCHEMA_DIALECT.to_string());
+        schema["title"] = serde_json::Value::String(self.type_name.clone());
+        schema["description"] =
+            serde_json::Value::String(format!("{} (when: {})", self.description, self.scenario));
+        schema
+    }
 }
 
 pub trait OrchResponseVariant: Send + Sync {
@@ -33,4 +72,29 @@ pub trait OrchResponseVariant: Send + Sync {
 pub trait OrchResponseVariants<T>: DynClone + Send + Sync {
     fn variants(&self) -> Vec<ResponseOption>;
     fn parse(&self, response: &str) -> Result<T, serde_json::Error>;
+
+    /// Returns a JSON Schema (draft 2020-12) which matches exactly one of the variants,
+    /// discriminated by the `response_type` field.
+    fn json_schema(&self) -> serde_json::Value {
+        let schemas = self
+            .variants()
+            .iter()
+            .map(|variant| {
+                let mut schema = variant.json_schema();
+                if let Some(schema) = schema.as_object_mut() {
+                    schema.remove("$schema");
+                }
+                schema
+            })
+            .collect::<Vec<_>>();
+        serde_json::json!({
+            "$schema": JSON_SCHEMA_DIALECT,
+            "oneOf": schemas,
+        })
+    }
+
+    /// Validates a response (as JSON) against [`OrchResponseVariants::json_schema`].
+    fn validate(&self, response: &serde_json::Value) -> Result<(), Vec<SchemaValidationError>> {
+        validate_json_schema(&self.json_schema(), response)
+    }
 }
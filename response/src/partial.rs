@@ -0,0 +1,98 @@
+use serde_json::Value;
+
+/// Parses the JSON object at the start of an incomplete response (e.g., while it is being streamed).
+///
+/// Unterminated strings, arrays and objects are closed, and values which cannot be completed
+/// (e.g., a key without a value, or a partial `true`) are dropped.
+/// Text before the first `{` and after the end of the object is ignored.
+///
+/// Returns `None` if no object has started yet.
+pub fn parse_partial_json(text: &str) -> Option<Value> {
+    let start = text.find('{')?;
+    let text = &text[start..];
+
+    let mut closers = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    // End of the text up to the last complete value, and the closers which complete it.
+    let mut safe_point = (0, Vec::new());
+    for (i, c) in text.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' | '[' => {
+                closers.push(if c == '{' { '}' } else { ']' });
+                safe_point = (i + 1, closers.clone());
+            }
+            '}' | ']' => {
+                closers.pop();
+                if closers.is_empty() {
+                    return serde_json::from_str(&text[..=i]).ok();
+                }
+                safe_point = (i + 1, closers.clone());
+            }
+            ',' => safe_point = (i, closers.clone()),
+            _ => {}
+        }
+    }
+
+    // Complete the text as is, so that partial strings and numbers are included.
+    let mut completed = text.to_string();
+    if in_string {
+        if escaped {
+            completed.pop();
+        }
+        completed.push('"');
+    }
+    completed.extend(closers.iter().rev());
+    if let Ok(value) = serde_json::from_str(&completed) {
+        return Some(value);
+    }
+
+    // Otherwise, drop the incomplete value.
+    let (end, closers) = safe_point;
+    let mut completed = text[..end].to_string();
+    completed.extend(closers.iter().rev());
+    serde_json::from_str(&completed).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_partial_json() {
+        assert_eq!(parse_partial_json("Here is the JSON: "), None);
+        assert_eq!(parse_partial_json("```json\n{"), Some(json!({})));
+        assert_eq!(
+            parse_partial_json(r#"{"response_type": "Answer", "suggestions": ["Use fewer adjec"#),
+            Some(json!({ "response_type": "Answer", "suggestions": ["Use fewer adjec"] }))
+        );
+        assert_eq!(
+            parse_partial_json(r#"{"a": {"b": 1}, "c": tr"#),
+            Some(json!({ "a": { "b": 1 } }))
+        );
+        assert_eq!(
+            parse_partial_json(r#"{"a": "x\"y", "b""#),
+            Some(json!({ "a": "x\"y" }))
+        );
+        assert_eq!(
+            parse_partial_json(r#"{"a": "line\"#),
+            Some(json!({ "a": "line" }))
+        );
+        assert_eq!(
+            parse_partial_json("{\"a\": 1}\n```"),
+            Some(json!({ "a": 1 }))
+        );
+    }
+}
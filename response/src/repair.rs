@@ -0,0 +1,206 @@
+/// A repair applied to a response by [`repair_json`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JsonRepair {
+    /// The JSON was wrapped in a Markdown code fence (e.g., "```json ... ```").
+    StrippedCodeFence,
+    /// Text around the JSON object (e.g., "Here is the JSON:") was removed.
+    ExtractedObject,
+    /// Trailing commas before a closing `}` or `]` were removed.
+    RemovedTrailingCommas,
+    /// Single-quoted strings were replaced with double-quoted strings.
+    ReplacedSingleQuotes,
+}
+
+impl std::fmt::Display for JsonRepair {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            JsonRepair::StrippedCodeFence => "stripped Markdown code fence",
+            JsonRepair::ExtractedObject => "removed text around the JSON object",
+            JsonRepair::RemovedTrailingCommas => "removed trailing commas",
+            JsonRepair::ReplacedSingleQuotes => "replaced single-quoted strings",
+        };
+        write!(f, "{description}")
+    }
+}
+
+/// The result of [`repair_json`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepairedJson {
+    /// The (possibly repaired) JSON.
+    pub json: String,
+    /// The repairs which were applied, in order.
+    pub repairs: Vec<JsonRepair>,
+}
+
+/// Extracts the JSON object from a response of a language model and fixes common syntax errors.
+///
+/// Responses which are already valid JSON are returned as is. Otherwise, Markdown code fences are stripped,
+/// the first balanced JSON object is extracted, and trailing commas and single-quoted strings are fixed.
+/// The result is not guaranteed to be valid JSON.
+pub fn repair_json(text: &str) -> RepairedJson {
+    if serde_json::from_str::<serde_json::Value>(text).is_ok() {
+        return RepairedJson {
+            json: text.to_string(),
+            repairs: Vec::new(),
+        };
+    }
+
+    let mut repairs = Vec::new();
+    let mut json = text.trim();
+    if let Some(fenced) = strip_code_fence(json) {
+        json = fenced.trim();
+        repairs.push(JsonRepair::StrippedCodeFence);
+    }
+    if let Some(object) = first_balanced_object(json) {
+        if object.len() != json.len() {
+            json = object;
+            repairs.push(JsonRepair::ExtractedObject);
+        }
+    }
+    let json = fix_syntax(json, &mut repairs);
+    RepairedJson { json, repairs }
+}
+
+/// Returns the contents of the first Markdown code fence, if there is one.
+fn strip_code_fence(text: &str) -> Option<&str> {
+    let start = text.find("```")?;
+    let fenced = &text[start + 3..];
+    // Skip the language tag (e.g., "json").
+    let fenced = match fenced.find('\n') {
+        Some(newline)
+            if fenced[..newline]
+                .trim()
+                .chars()
+                .all(|c| c.is_alphanumeric()) =>
+        {
+            &fenced[newline + 1..]
+        }
+        _ => fenced,
+    };
+    let end = fenced.find("```").unwrap_or(fenced.len());
+    Some(&fenced[..end])
+}
+
+/// Returns the first balanced JSON object, skipping braces inside (single or double-quoted) strings.
+fn first_balanced_object(text: &str) -> Option<&str> {
+    let start = text.find('{')?;
+    let mut depth = 0;
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in text[start..].char_indices() {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some(_) if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None => match c {
+                '"' | '\'' => quote = Some(c),
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(&text[start..start + i + 1]);
+                    }
+                }
+                _ => {}
+            },
+        }
+    }
+    None
+}
+
+/// Removes trailing commas and replaces single-quoted strings with double-quoted strings.
+fn fix_syntax(json: &str, repairs: &mut Vec<JsonRepair>) -> String {
+    let mut fixed = String::with_capacity(json.len());
+    let mut removed_trailing_commas = false;
+    let mut replaced_single_quotes = false;
+    let mut chars = json.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                fixed.push(c);
+                while let Some(c) = chars.next() {
+                    fixed.push(c);
+                    match c {
+                        '\\' => fixed.extend(chars.next()),
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '\'' => {
+                replaced_single_quotes = true;
+                fixed.push('"');
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => match chars.next() {
+                            Some('\'') => fixed.push('\''),
+                            Some(escaped) => {
+                                fixed.push('\\');
+                                fixed.push(escaped);
+                            }
+                            None => {}
+                        },
+                        '"' => fixed.push_str("\\\""),
+                        '\'' => break,
+                        c => fixed.push(c),
+                    }
+                }
+                fixed.push('"');
+            }
+            ',' => {
+                let rest = chars.clone().find(|c| !c.is_whitespace());
+                if matches!(rest, Some('}' | ']')) {
+                    removed_trailing_commas = true;
+                } else {
+                    fixed.push(c);
+                }
+            }
+            c => fixed.push(c),
+        }
+    }
+    if removed_trailing_commas {
+        repairs.push(JsonRepair::RemovedTrailingCommas);
+    }
+    if replaced_single_quotes {
+        repairs.push(JsonRepair::ReplacedSingleQuotes);
+    }
+    fixed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repair_json() {
+        let valid = r#"{"response_type": "Answer", "capital": "London"}"#;
+        assert_eq!(repair_json(valid).json, valid);
+        assert!(repair_json(valid).repairs.is_empty());
+
+        let response = "Here is the JSON:\n```json\n{'response_type': 'Answer', \"capital\": \"Lon{don}\", 'note': 'a \"b\"',}\n```\nLet me know!";
+        let repaired = repair_json(response);
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&repaired.json).unwrap(),
+            serde_json::json!({ "response_type": "Answer", "capital": "Lon{don}", "note": "a \"b\"" })
+        );
+        assert_eq!(
+            repaired.repairs,
+            vec![
+                JsonRepair::StrippedCodeFence,
+                JsonRepair::RemovedTrailingCommas,
+                JsonRepair::ReplacedSingleQuotes
+            ]
+        );
+
+        let repaired = repair_json(r#"Sure! {"items": [1, 2,], "ok": true} Hope this helps."#);
+        assert_eq!(repaired.json, r#"{"items": [1, 2], "ok": true}"#);
+        assert_eq!(
+            repaired.repairs,
+            vec![
+                JsonRepair::ExtractedObject,
+                JsonRepair::RemovedTrailingCommas
+            ]
+        );
+    }
+}
@@ -0,0 +1,173 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde_json::{json, Value};
+
+use crate::{validate_json_schema, ResponseSchemaField};
+
+/// A type which can be used as a field of a response variant.
+///
+/// Implemented for strings, booleans, numbers, [`Option`], [`Vec`] and maps with string keys,
+/// and derived with `#[derive(Variant)]` or `#[derive(ResponseSchema)]` for nested structs and unit enums.
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` cannot be used as a field of a response variant",
+    label = "unsupported field type",
+    note = "nested structs and unit enums should `#[derive(ResponseSchema)]` (or `#[derive(Variant)]`)"
+)]
+pub trait OrchResponseSchema {
+    /// Short name of the type, as described to the model (e.g., "string", "integer?" or "string[]").
+    fn schema_type() -> String;
+
+    /// JSON Schema of the type.
+    fn json_schema() -> Value;
+
+    /// Whether a field of the type may be omitted from the response
+    /// (i.e., an [`Option`], which serde deserializes as `None` when it is missing).
+    fn is_optional() -> bool {
+        false
+    }
+}
+
+/// Returns a JSON Schema of an object with the fields.
+///
+/// All fields which are not [`ResponseSchemaField::optional`] are required, and no additional fields are allowed.
+pub fn object_json_schema(fields: &[ResponseSchemaField]) -> Value {
+    let mut properties = serde_json::Map::new();
+    let mut required = Vec::new();
+    for field in fields {
+        properties.insert(field.name.clone(), field.json_schema());
+        if !field.optional {
+            required.push(field.name.clone());
+        }
+    }
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+        "additionalProperties": false,
+    })
+}
+
+/// Returns a short description of an object with the fields (e.g., "{name: string, age?: integer}"),
+/// where optional fields are marked with a `?` suffix.
+pub fn object_schema_type(fields: &[ResponseSchemaField]) -> String {
+    let fields = fields
+        .iter()
+        .map(|field| {
+            let optional = if field.optional { "?" } else { "" };
+            format!("{}{}: {}", field.name, optional, field.typ)
+        })
+        .collect::<Vec<_>>();
+    format!("{{{}}}", fields.join(", "))
+}
+
+impl ResponseSchemaField {
+    /// Returns the JSON Schema of the field, including its description and example.
+    pub fn json_schema(&self) -> Value {
+        let mut schema = if self.schema.is_null() {
+            crate::field_type_json_schema(&self.typ)
+        } else {
+            self.schema.clone()
+        };
+        // Examples of non-string fields (e.g., numbers) are given as JSON.
+        let example = serde_json::from_str::<Value>(&self.example)
+            .ok()
+            .filter(|example| {
+                !example.is_string() && validate_json_schema(&schema, example).is_ok()
+            })
+            .unwrap_or_else(|| Value::String(self.example.clone()));
+        schema["description"] = Value::String(self.description.clone());
+        schema["examples"] = json!([example]);
+        schema
+    }
+}
+
+macro_rules! impl_primitive_schema {
+    ($typ:literal: $($t:ty),*) => {
+        $(
+            impl OrchResponseSchema for $t {
+                fn schema_type() -> String {
+                    $typ.to_string()
+                }
+
+                fn json_schema() -> Value {
+                    json!({ "type": $typ })
+                }
+            }
+        )*
+    };
+}
+
+impl_primitive_schema!("string": String, char);
+impl_primitive_schema!("boolean": bool);
+impl_primitive_schema!("integer": i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+impl_primitive_schema!("number": f32, f64);
+
+impl<T: OrchResponseSchema> OrchResponseSchema for Option<T> {
+    fn schema_type() -> String {
+        format!("{}?", T::schema_type())
+    }
+
+    fn json_schema() -> Value {
+        let schema = T::json_schema();
+        match schema.get("type") {
+            Some(Value::String(typ)) if schema.get("enum").is_none() => {
+                let mut schema = schema.clone();
+                schema["type"] = json!([typ, "null"]);
+                schema
+            }
+            _ => json!({ "anyOf": [schema, { "type": "null" }] }),
+        }
+    }
+
+    fn is_optional() -> bool {
+        true
+    }
+}
+
+impl<T: OrchResponseSchema> OrchResponseSchema for Vec<T> {
+    fn schema_type() -> String {
+        format!("{}[]", T::schema_type())
+    }
+
+    fn json_schema() -> Value {
+        json!({ "type": "array", "items": T::json_schema() })
+    }
+}
+
+impl<T: OrchResponseSchema, S> OrchResponseSchema for HashMap<String, T, S> {
+    fn schema_type() -> String {
+        format!("map<string, {}>", T::schema_type())
+    }
+
+    fn json_schema() -> Value {
+        json!({ "type": "object", "additionalProperties": T::json_schema() })
+    }
+}
+
+impl<T: OrchResponseSchema> OrchResponseSchema for BTreeMap<String, T> {
+    fn schema_type() -> String {
+        format!("map<string, {}>", T::schema_type())
+    }
+
+    fn json_schema() -> Value {
+        json!({ "type": "object", "additionalProperties": T::json_schema() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_composite_schemas() {
+        assert_eq!(Option::<Vec<u32>>::schema_type(), "integer[]?");
+        assert_eq!(
+            Option::<Vec<u32>>::json_schema(),
+            json!({ "type": ["array", "null"], "items": { "type": "integer" } })
+        );
+        assert_eq!(
+            HashMap::<String, f64>::json_schema(),
+            json!({ "type": "object", "additionalProperties": { "type": "number" } })
+        );
+    }
+}
@@ -1,4 +1,5 @@
 use darling::FromMeta;
+use syn::{punctuated::Punctuated, Token};
 
 /// #[variant(...)]
 #[derive(Debug, FromMeta)]
@@ -14,3 +15,173 @@ pub(crate) struct SchemaAttribute {
     pub(crate) description: String,
     pub(crate) example: String,
 }
+
+/// The subset of #[serde(...)] attributes on a struct or an enum which affects how it is deserialized.
+#[derive(Debug, Default)]
+pub(crate) struct SerdeContainerAttribute {
+    /// `rename_all = "..."` (or `rename_all(deserialize = "...")`).
+    pub(crate) rename_all: Option<RenameRule>,
+    /// `default`, which makes all fields optional.
+    pub(crate) default: bool,
+}
+
+/// The subset of #[serde(...)] attributes on a field or an enum variant which affects how it is deserialized.
+#[derive(Debug, Default)]
+pub(crate) struct SerdeFieldAttribute {
+    /// `rename = "..."` (or `rename(deserialize = "...")`).
+    pub(crate) rename: Option<String>,
+    /// `default` or `default = "..."`, which makes the field optional.
+    pub(crate) default: bool,
+    /// `skip` or `skip_deserializing`, which removes the field from the schema.
+    pub(crate) skip: bool,
+    /// `flatten`, which is not supported.
+    pub(crate) flatten: Option<syn::Path>,
+}
+
+impl SerdeContainerAttribute {
+    pub(crate) fn from_attrs(attrs: &[syn::Attribute]) -> syn::Result<Self> {
+        let mut container = Self::default();
+        for meta in serde_metas(attrs)? {
+            match &meta {
+                meta if meta.path().is_ident("rename_all") => {
+                    if let Some(rename_all) = deserialize_name(meta)? {
+                        container.rename_all = Some(RenameRule::from_lit(&rename_all)?);
+                    }
+                }
+                syn::Meta::Path(path) if path.is_ident("default") => container.default = true,
+                syn::Meta::NameValue(meta) if meta.path.is_ident("default") => container.default = true,
+                _ => {}
+            }
+        }
+        Ok(container)
+    }
+}
+
+impl SerdeFieldAttribute {
+    pub(crate) fn from_attrs(attrs: &[syn::Attribute]) -> syn::Result<Self> {
+        let mut field = Self::default();
+        for meta in serde_metas(attrs)? {
+            let path = meta.path();
+            if path.is_ident("rename") {
+                if let Some(rename) = deserialize_name(&meta)? {
+                    field.rename = Some(rename.value());
+                }
+            } else if path.is_ident("default") {
+                field.default = true;
+            } else if path.is_ident("skip") || path.is_ident("skip_deserializing") {
+                field.skip = true;
+            } else if path.is_ident("flatten") {
+                field.flatten = Some(path.clone());
+            }
+        }
+        Ok(field)
+    }
+}
+
+/// Parses the arguments of all #[serde(...)] attributes.
+fn serde_metas(attrs: &[syn::Attribute]) -> syn::Result<Vec<syn::Meta>> {
+    let mut metas = Vec::new();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("serde")) {
+        metas.extend(attr.parse_args_with(Punctuated::<syn::Meta, Token![,]>::parse_terminated)?);
+    }
+    Ok(metas)
+}
+
+/// Returns the name used for deserialization in `key = "..."` or `key(deserialize = "...")`.
+fn deserialize_name(meta: &syn::Meta) -> syn::Result<Option<syn::LitStr>> {
+    match meta {
+        syn::Meta::NameValue(meta) => match &meta.value {
+            syn::Expr::Lit(syn::ExprLit {
+                lit: syn::Lit::Str(lit), ..
+            }) => Ok(Some(lit.clone())),
+            value => Err(syn::Error::new_spanned(value, "Expected a string literal")),
+        },
+        syn::Meta::List(list) => {
+            let metas = list.parse_args_with(Punctuated::<syn::Meta, Token![,]>::parse_terminated)?;
+            match metas.iter().find(|meta| meta.path().is_ident("deserialize")) {
+                Some(meta @ syn::Meta::NameValue(_)) => deserialize_name(meta),
+                _ => Ok(None),
+            }
+        }
+        syn::Meta::Path(path) => Err(syn::Error::new_spanned(path, "Expected a value")),
+    }
+}
+
+/// A case convention of `#[serde(rename_all = "...")]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RenameRule {
+    Lower,
+    Upper,
+    Pascal,
+    Camel,
+    Snake,
+    ScreamingSnake,
+    Kebab,
+    ScreamingKebab,
+}
+
+impl RenameRule {
+    fn from_lit(lit: &syn::LitStr) -> syn::Result<Self> {
+        match lit.value().as_str() {
+            "lowercase" => Ok(Self::Lower),
+            "UPPERCASE" => Ok(Self::Upper),
+            "PascalCase" => Ok(Self::Pascal),
+            "camelCase" => Ok(Self::Camel),
+            "snake_case" => Ok(Self::Snake),
+            "SCREAMING_SNAKE_CASE" => Ok(Self::ScreamingSnake),
+            "kebab-case" => Ok(Self::Kebab),
+            "SCREAMING-KEBAB-CASE" => Ok(Self::ScreamingKebab),
+            rule => Err(syn::Error::new_spanned(lit, format!("Unknown rename rule `rename_all = {rule:?}`"))),
+        }
+    }
+
+    /// Applies the rule to a field name, which is assumed to be in snake_case (same as serde).
+    pub(crate) fn apply_to_field(self, field: &str) -> String {
+        match self {
+            Self::Lower | Self::Snake => field.to_string(),
+            Self::Upper | Self::ScreamingSnake => field.to_ascii_uppercase(),
+            Self::Pascal => field
+                .split('_')
+                .map(|word| {
+                    let mut chars = word.chars();
+                    chars
+                        .next()
+                        .map(|first| first.to_ascii_uppercase().to_string() + chars.as_str())
+                        .unwrap_or_default()
+                })
+                .collect(),
+            Self::Camel => Self::Camel.apply_to_variant(&Self::Pascal.apply_to_field(field)),
+            Self::Kebab => field.replace('_', "-"),
+            Self::ScreamingKebab => field.to_ascii_uppercase().replace('_', "-"),
+        }
+    }
+
+    /// Applies the rule to an enum variant name, which is assumed to be in PascalCase (same as serde).
+    pub(crate) fn apply_to_variant(self, variant: &str) -> String {
+        match self {
+            Self::Pascal => variant.to_string(),
+            Self::Lower => variant.to_ascii_lowercase(),
+            Self::Upper => variant.to_ascii_uppercase(),
+            Self::Camel => {
+                let mut chars = variant.chars();
+                chars
+                    .next()
+                    .map(|first| first.to_ascii_lowercase().to_string() + chars.as_str())
+                    .unwrap_or_default()
+            }
+            Self::Snake | Self::ScreamingSnake | Self::Kebab | Self::ScreamingKebab => {
+                let mut snake = String::new();
+                for (i, ch) in variant.char_indices() {
+                    if i > 0 && ch.is_uppercase() {
+                        snake.push('_');
+                    }
+                    snake.push(ch.to_ascii_lowercase());
+                }
+                match self {
+                    Self::Snake => snake,
+                    rule => rule.apply_to_field(&snake),
+                }
+            }
+        }
+    }
+}
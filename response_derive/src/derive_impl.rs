@@ -1,36 +1,49 @@
 use darling::FromMeta;
-use quote::quote;
-use syn::{parse_macro_input, spanned::Spanned, DeriveInput, PathArguments};
+use quote::{quote, quote_spanned};
+use syn::{ext::IdentExt, parse_macro_input, spanned::Spanned, DeriveInput};
 
-use crate::attribute_impl::{SchemaAttribute, VariantAttribute};
+use crate::attribute_impl::{SchemaAttribute, SerdeContainerAttribute, SerdeFieldAttribute, VariantAttribute};
 
 pub(crate) fn response_variants_derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
-    let mut output = quote!();
-
-    // Bring traits into scope.
-    output.extend(quote! {
-        use ::orch_response::OrchResponseVariant;
-        use ::serde::de::Error;
-    });
-
-    let original_enum = parse_macro_input!(input as DeriveInput);
-    let DeriveInput { data, ident, .. } = original_enum.clone();
-    let syn::Data::Enum(data) = data else {
-        panic!("#[derive(OrchResponseVariants)] can only be used with enums");
+    let input = parse_macro_input!(input as DeriveInput);
+    match response_variants_derive_impl(&input) {
+        Ok(output) => output.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn response_variants_derive_impl(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let original_enum_ident = &input.ident;
+    let syn::Data::Enum(data) = &input.data else {
+        return Err(syn::Error::new_spanned(
+            original_enum_ident,
+            "#[derive(Variants)] can only be used with enums",
+        ));
     };
-    let original_enum_ident = ident;
+    if data.variants.is_empty() {
+        return Err(syn::Error::new_spanned(
+            original_enum_ident,
+            "#[derive(Variants)] requires at least one variant",
+        ));
+    }
 
     let vec_capacity = data.variants.len();
 
     let mut options_vec_pushes = quote!();
+    // Note: We parse with a dynamic evaluation and looking at the `response_type` field, but this could be done
+    // by deriving #[serde(tag = "response_type")] on the enum.
+    let mut response_type_arms = quote!();
     for variant in data.variants.iter() {
-        let ident = syn::Ident::new(
-            &get_enum_variant_struct_ident(variant).expect("Failed to parse enum variant"),
-            variant.ident.span(),
-        );
+        let variant_ty = get_enum_variant_struct_type(variant)?;
+        let variant_ident = &variant.ident;
 
-        options_vec_pushes.extend(quote! {
-            options.push(#ident::variant());
+        options_vec_pushes.extend(quote_spanned! {variant_ty.span()=>
+            options.push(<#variant_ty as ::orch_response::OrchResponseVariant>::variant());
+        });
+        response_type_arms.extend(quote! {
+            response_type if response_type == <#variant_ty as ::orch_response::OrchResponseVariant>::variant().type_name => {
+                Ok(#original_enum_ident::#variant_ident(::orch_response::serde_json::from_str::<#variant_ty>(response)?))
+            }
         });
     }
 
@@ -38,27 +51,10 @@ pub(crate) fn response_variants_derive(input: proc_macro::TokenStream) -> proc_m
     // NOTE: This is hacky, but a workaround for the fact that the enum cannot be constructed.
     let derived_enum_struct_ident = syn::Ident::new(&format!("{}Derived", original_enum_ident), original_enum_ident.span());
 
-    output.extend(quote! {
+    Ok(quote! {
         #[derive(::std::fmt::Debug, ::std::clone::Clone)]
         pub struct #derived_enum_struct_ident;
-    });
 
-    // Note: We parse with a dynamic evaluation and looking at the `response_type` field, but this could be done
-    // by deriving #[serde(tag = "response_type")] on the enum.
-    let mut response_type_arms = quote!();
-    for variant in data.variants.iter() {
-        let variant_ident = variant.ident.clone();
-        let variant_ident_str = syn::LitStr::new(&variant.ident.to_string(), variant.ident.span());
-        let struct_ident = syn::Ident::new(
-            &get_enum_variant_struct_ident(variant).expect("Failed to parse enum variant"),
-            variant.ident.span(),
-        );
-        response_type_arms.extend(quote! {
-            #variant_ident_str => Ok(#original_enum_ident::#variant_ident(serde_json::from_str::<#struct_ident>(response)?)),
-        });
-    }
-
-    output.extend(quote! {
         impl ::orch_response::OrchResponseVariants<#original_enum_ident> for #derived_enum_struct_ident {
             fn variants(&self) -> Vec<::orch_response::ResponseOption> {
                 let mut options = Vec::with_capacity(#vec_capacity);
@@ -66,94 +62,72 @@ pub(crate) fn response_variants_derive(input: proc_macro::TokenStream) -> proc_m
                 options
             }
 
-            fn parse(&self, response: &str) -> Result<#original_enum_ident, ::serde_json::Error> {
-                let dynamic_parsed = serde_json::from_str::<serde_json::Value>(response)?;
-                let response_type = dynamic_parsed.get("response_type");
-                let response_type = match response_type {
-                    Some(response_type) => match response_type.as_str() {
-                        Some(response_type) => response_type,
-                        None => {
-                            return Err(::serde_json::Error::custom(format!(
-                                "Invalid response type: {}",
-                                response
-                            )));
-                        }
-                    }
-                    None => {
-                        return Err(::serde_json::Error::custom(format!(
-                            "Invalid response type: {}",
-                            response
-                        )));
-                    }
+            fn parse(&self, response: &str) -> Result<#original_enum_ident, ::orch_response::serde_json::Error> {
+                use ::orch_response::serde::de::Error;
+
+                let dynamic_parsed = ::orch_response::serde_json::from_str::<::orch_response::serde_json::Value>(response)?;
+                let Some(response_type) = dynamic_parsed.get("response_type").and_then(|response_type| response_type.as_str()) else {
+                    return Err(::orch_response::serde_json::Error::custom(format!(
+                        "Invalid response type: {}",
+                        response
+                    )));
                 };
                 match response_type {
                     #response_type_arms
-                    _ => Err(::serde_json::Error::custom("Invalid response type")),
+                    _ => Err(::orch_response::serde_json::Error::custom("Invalid response type")),
                 }
             }
         }
-    });
-
-    output.into()
+    })
 }
 
 pub fn response_variant_derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
-    let original_struct = parse_macro_input!(input as DeriveInput);
-    let DeriveInput { data, ident, attrs, .. } = original_struct.clone();
-    let syn::Data::Struct(data) = data else {
-        panic!("#[derive(OrchResponseOption)] can only be used with structs");
-    };
-    let original_struct_ident = ident.clone();
+    let input = parse_macro_input!(input as DeriveInput);
+    match response_variant_derive_impl(&input) {
+        Ok(output) => output.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
 
-    let fields = data.fields;
+fn response_variant_derive_impl(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let original_struct_ident = &input.ident;
+    let syn::Data::Struct(data) = &input.data else {
+        return Err(syn::Error::new_spanned(
+            original_struct_ident,
+            "#[derive(Variant)] can only be used with structs",
+        ));
+    };
 
     // Parse the #[variant(...)] attribute.
-    let variant_attr = attrs
+    let variant_attrs = input
+        .attrs
         .iter()
-        .filter_map(|attr| VariantAttribute::from_meta(&attr.meta).ok())
-        .next()
-        .expect("#[variant(...)] attribute not found on variant field");
+        .filter(|attr| attr.path().is_ident("variant"))
+        .collect::<Vec<_>>();
+    let variant_attr = match variant_attrs.as_slice() {
+        [variant_attr] => variant_attr,
+        [] => {
+            return Err(syn::Error::new_spanned(
+                original_struct_ident,
+                "Expected a #[variant(variant = \"...\", scenario = \"...\", description = \"...\")] attribute",
+            ));
+        }
+        [_, duplicate, ..] => {
+            return Err(syn::Error::new_spanned(duplicate, "Duplicate #[variant(...)] attribute"));
+        }
+    };
     let VariantAttribute {
         variant,
         scenario,
         description,
-    } = variant_attr;
+    } = VariantAttribute::from_meta(&variant_attr.meta).map_err(|e| syn::Error::new_spanned(variant_attr, e))?;
 
     // Parse the fields used in [`orch::response::OrchResponseVariant`].
-    let mut schema_fields = Vec::new();
-    for variant_field in fields.iter() {
-        // Parse the #[schema(...)] attribute.
-        let schema_attr = variant_field
-            .attrs
-            .iter()
-            .filter_map(|attr| SchemaAttribute::from_meta(&attr.meta).ok())
-            .collect::<Vec<_>>();
-        if schema_attr.len() != 1 {
-            panic!("Expected a single #[schema(...)] attribute for each field of the enum variant with the correct format and parameters");
-        }
-        let SchemaAttribute { description, example } = schema_attr.first().expect("Failed to parse schema attribute");
+    let schema_fields = schema_fields(&data.fields, &SerdeContainerAttribute::from_attrs(&input.attrs)?)?;
 
-        let typ = ast_type_to_str(&variant_field.ty).unwrap_or_else(|_| {
-            panic!(
-                "Failed to convert type to string for field `{}` of variant `{}`",
-                variant_field.ident.as_ref().unwrap(),
-                ident
-            )
-        });
-        let typ = syn::LitStr::new(&typ, variant_field.span());
-        let field_ident = syn::LitStr::new(&variant_field.ident.as_ref().unwrap().to_string(), variant_field.span());
-        schema_fields.push(quote! {
-            ::orch_response::ResponseSchemaField {
-                name: #field_ident.to_string(),
-                description: #description.to_string(),
-                typ: #typ.to_string(),
-                example: #example.to_string(),
-            }
-        })
-    }
-
-    quote! {
-        impl ::orch_response::OrchResponseVariant for #original_struct_ident {
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::orch_response::OrchResponseVariant for #original_struct_ident #ty_generics #where_clause {
             fn variant() -> ::orch_response::ResponseOption {
                 ::orch_response::ResponseOption {
                     type_name: #variant.to_string(),
@@ -165,88 +139,271 @@ pub fn response_variant_derive(input: proc_macro::TokenStream) -> proc_macro::To
                 }
             }
         }
+
+        // Variants can also be nested in other variants.
+        impl #impl_generics ::orch_response::OrchResponseSchema for #original_struct_ident #ty_generics #where_clause {
+            fn schema_type() -> String {
+                ::orch_response::object_schema_type(&[#(#schema_fields),*])
+            }
+
+            fn json_schema() -> ::orch_response::serde_json::Value {
+                ::orch_response::object_json_schema(&[#(#schema_fields),*])
+            }
+        }
+    })
+}
+
+pub fn response_schema_derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match response_schema_derive_impl(&input) {
+        Ok(output) => output.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn response_schema_derive_impl(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let ident = &input.ident;
+    let serde_container = SerdeContainerAttribute::from_attrs(&input.attrs)?;
+    let (schema_type, json_schema) = match &input.data {
+        syn::Data::Struct(data) => {
+            let schema_fields = schema_fields(&data.fields, &serde_container)?;
+            (
+                quote! { ::orch_response::object_schema_type(&[#(#schema_fields),*]) },
+                quote! { ::orch_response::object_json_schema(&[#(#schema_fields),*]) },
+            )
+        }
+        syn::Data::Enum(data) => {
+            // Unit enums are represented as string enums.
+            let mut names = Vec::new();
+            for variant in data.variants.iter() {
+                if !matches!(variant.fields, syn::Fields::Unit) {
+                    return Err(syn::Error::new_spanned(
+                        variant,
+                        "#[derive(ResponseSchema)] only supports enums with unit variants",
+                    ));
+                }
+                // Use the names which serde deserializes.
+                let serde_variant = SerdeFieldAttribute::from_attrs(&variant.attrs)?;
+                if serde_variant.skip {
+                    continue;
+                }
+                let name = match (serde_variant.rename, serde_container.rename_all) {
+                    (Some(rename), _) => rename,
+                    (None, Some(rename_all)) => rename_all.apply_to_variant(&variant.ident.unraw().to_string()),
+                    (None, None) => variant.ident.unraw().to_string(),
+                };
+                names.push(name);
+            }
+            let schema_type = format!("enum({})", names.join("|"));
+            (
+                quote! { #schema_type.to_string() },
+                quote! { ::orch_response::serde_json::json!({ "type": "string", "enum": [#(#names),*] }) },
+            )
+        }
+        syn::Data::Union(_) => {
+            return Err(syn::Error::new_spanned(
+                ident,
+                "#[derive(ResponseSchema)] can only be used with structs and enums",
+            ));
+        }
+    };
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::orch_response::OrchResponseSchema for #ident #ty_generics #where_clause {
+            fn schema_type() -> String {
+                #schema_type
+            }
+
+            fn json_schema() -> ::orch_response::serde_json::Value {
+                #json_schema
+            }
+        }
+    })
+}
+
+/// Constructs a [`orch_response::ResponseSchemaField`] for each field, as described by its #[schema(...)] attribute.
+/// The type of the field is described by its [`orch_response::OrchResponseSchema`] implementation.
+///
+/// The name and optionality of the field follow its #[serde(...)] attributes (and `Option` type), so that the schema matches what is deserialized.
+fn schema_fields(fields: &syn::Fields, serde_container: &SerdeContainerAttribute) -> syn::Result<Vec<proc_macro2::TokenStream>> {
+    let syn::Fields::Named(fields) = fields else {
+        return Err(syn::Error::new_spanned(fields, "Expected a struct with named fields"));
+    };
+
+    let mut schema_fields = Vec::new();
+    for field in fields.named.iter() {
+        let serde_field = SerdeFieldAttribute::from_attrs(&field.attrs)?;
+        if let Some(flatten) = serde_field.flatten {
+            return Err(syn::Error::new_spanned(
+                flatten,
+                "#[serde(flatten)] is not supported in response schemas",
+            ));
+        }
+        if serde_field.skip {
+            // Skipped fields are not deserialized, so they are not part of the schema.
+            continue;
+        }
+
+        let schema_attrs = field.attrs.iter().filter(|attr| attr.path().is_ident("schema")).collect::<Vec<_>>();
+        let schema_attr = match schema_attrs.as_slice() {
+            [schema_attr] => schema_attr,
+            [] => {
+                return Err(syn::Error::new_spanned(
+                    field,
+                    "Expected a #[schema(description = \"...\", example = \"...\")] attribute",
+                ))
+            }
+            [_, duplicate, ..] => {
+                return Err(syn::Error::new_spanned(duplicate, "Duplicate #[schema(...)] attribute"));
+            }
+        };
+        let SchemaAttribute { description, example } =
+            SchemaAttribute::from_meta(&schema_attr.meta).map_err(|e| syn::Error::new_spanned(schema_attr, e))?;
+
+        let ty = &field.ty;
+        let ident = field.ident.as_ref().unwrap().unraw().to_string();
+        let name = match (serde_field.rename, serde_container.rename_all) {
+            (Some(rename), _) => rename,
+            (None, Some(rename_all)) => rename_all.apply_to_field(&ident),
+            (None, None) => ident,
+        };
+        let field_name = syn::LitStr::new(&name, field.span());
+        // Fields with a default, and `Option` fields, are deserialized when missing.
+        let optional_attr = serde_field.default || serde_container.default;
+        let optional = quote_spanned! {ty.span()=> #optional_attr || <#ty as ::orch_response::OrchResponseSchema>::is_optional() };
+        // Spanned on the type, so that unsupported types are reported on the field.
+        let typ = quote_spanned! {ty.span()=> <#ty as ::orch_response::OrchResponseSchema>::schema_type() };
+        let schema = quote_spanned! {ty.span()=> <#ty as ::orch_response::OrchResponseSchema>::json_schema() };
+        schema_fields.push(quote! {
+            ::orch_response::ResponseSchemaField {
+                name: #field_name.to_string(),
+                description: #description.to_string(),
+                typ: #typ,
+                example: #example.to_string(),
+                schema: #schema,
+                optional: #optional,
+            }
+        })
     }
-    .into()
+    Ok(schema_fields)
 }
 
 // Parse `Answer(AnswerResponseOption)` into `AnswerResponseOption`.
-fn get_enum_variant_struct_ident(variant: &syn::Variant) -> Result<String, String> {
+fn get_enum_variant_struct_type(variant: &syn::Variant) -> syn::Result<&syn::Type> {
     // We expect the enum variant to look like this: `Answer(AnswerResponseOption)`,
     // so we parse the `AnswerResponseOption` struct.
-    let syn::Fields::Unnamed(fields) = &variant.fields else {
-        panic!("Expected an unnamed struct for each enum variant");
-    };
-    let Some(syn::Field { ty, .. }) = fields.unnamed.first() else {
-        panic!("Expected an unnamed struct for each enum variant");
-    };
-    let syn::Type::Path(p) = ty else {
-        panic!("Expected an unnamed struct for each enum variant");
-    };
-    let ident = &p.path.segments.first().unwrap().ident;
-    Ok(ident.to_string())
+    match &variant.fields {
+        syn::Fields::Unnamed(fields) if fields.unnamed.len() == 1 => Ok(&fields.unnamed.first().unwrap().ty),
+        _ => Err(syn::Error::new_spanned(
+            variant,
+            format!(
+                "Expected a tuple variant with a single struct which derives `Variant` (e.g., `{}({}Variant)`)",
+                variant.ident, variant.ident
+            ),
+        )),
+    }
 }
 
-fn ast_type_to_str(ty: &syn::Type) -> Result<String, String> {
-    match ty {
-        syn::Type::Path(tp) => {
-            let ps = tp.path.segments.first();
-            let Some(first_path_segment) = ps else {
-                return Err(format!("Unsupported/unexpected type: {:?}", ty).to_owned());
-            };
-            let t = first_path_segment.ident.to_string();
-            match t.as_ref() {
-                "String" => {
-                    // SUPPORTED: String
-                    Ok("string".to_owned())
-                }
-                "bool" => {
-                    // SUPPORTED: bool
-                    Ok("boolean".to_owned())
-                }
-                "Option" => {
-                    let PathArguments::AngleBracketed(ab) = &tp.path.segments.first().unwrap().arguments else {
-                        return Err(format!("Unsupported/unexpected type: {:?}", ty).to_owned());
-                    };
-                    let syn::GenericArgument::Type(t) = ab.args.first().unwrap() else {
-                        return Err(format!("Unsupported/unexpected type: {:?}", ty).to_owned());
-                    };
-                    let syn::Type::Path(p) = t else {
-                        return Err(format!("Unsupported/unexpected type: {:?}", ty).to_owned());
-                    };
-                    let t = p.path.segments.first().unwrap().ident.to_string();
-
-                    match t.as_ref() {
-                        "String" => {
-                            // SUPPORTED: Option<String>
-                            Ok("string?".to_owned())
-                        }
-                        "bool" => {
-                            // SUPPORTED: Option<bool>
-                            Ok("boolean?".to_owned())
-                        }
-                        _ => Err(format!("Unsupported/unexpected type: {}", t).to_owned()),
-                    }
-                }
-                "Vec" => {
-                    let PathArguments::AngleBracketed(ab) = &tp.path.segments.first().unwrap().arguments else {
-                        return Err(format!("Unsupported/unexpected type: {:?}", ty).to_owned());
-                    };
-                    let syn::GenericArgument::Type(t) = ab.args.first().unwrap() else {
-                        return Err(format!("Unsupported/unexpected type: {:?}", ty).to_owned());
-                    };
-                    let syn::Type::Path(p) = t else {
-                        return Err(format!("Unsupported/unexpected type: {:?}", ty).to_owned());
-                    };
-                    let t = p.path.segments.first().unwrap().ident.to_string();
-                    match t.as_ref() {
-                        // SUPPORTED: Vec<String>
-                        "String" => Ok("string[]".to_owned()),
-                        _ => Err(format!("Unsupported/unexpected type: {}", t).to_owned()),
-                    }
-                }
-                _ => Err(format!("Unsupported/unexpected type: {}", t).to_owned()),
+#[cfg(test)]
+mod tests {
+    use syn::parse_quote;
+
+    use super::*;
+
+    fn error_message(result: syn::Result<proc_macro2::TokenStream>) -> String {
+        result.expect_err("Expected a compile error").to_string()
+    }
+
+    #[test]
+    fn test_variants_derive_errors() {
+        let input: DeriveInput = parse_quote! {
+            struct ResponseOptions;
+        };
+        assert_eq!(
+            error_message(response_variants_derive_impl(&input)),
+            "#[derive(Variants)] can only be used with enums"
+        );
+
+        let input: DeriveInput = parse_quote! {
+            enum ResponseOptions {
+                Answer(AnswerResponseOption),
+                Fail { reason: String },
             }
-        }
-        _ => Err(format!("Unsupported/unexpected type: {:?}", ty).to_owned()),
+        };
+        assert_eq!(
+            error_message(response_variants_derive_impl(&input)),
+            "Expected a tuple variant with a single struct which derives `Variant` (e.g., `Fail(FailVariant)`)"
+        );
+    }
+
+    #[test]
+    fn test_variant_derive_errors() {
+        let input: DeriveInput = parse_quote! {
+            struct AnswerResponseOption {
+                #[schema(description = "Capital city", example = "London")]
+                capital: String,
+            }
+        };
+        assert!(error_message(response_variant_derive_impl(&input)).starts_with("Expected a #[variant("));
+
+        let input: DeriveInput = parse_quote! {
+            #[variant(variant = "Answer", scenario = "You know the answer", description = "Answer")]
+            #[variant(variant = "Fail", scenario = "You don't know the answer", description = "Fail")]
+            struct AnswerResponseOption {}
+        };
+        assert_eq!(
+            error_message(response_variant_derive_impl(&input)),
+            "Duplicate #[variant(...)] attribute"
+        );
+
+        let input: DeriveInput = parse_quote! {
+            #[variant(variant = "Answer", description = "Answer")]
+            struct AnswerResponseOption {}
+        };
+        assert_eq!(error_message(response_variant_derive_impl(&input)), "Missing field `scenario`");
+
+        let input: DeriveInput = parse_quote! {
+            #[variant(variant = "Answer", scenario = "You know the answer", description = "Answer")]
+            struct AnswerResponseOption(String);
+        };
+        assert_eq!(
+            error_message(response_variant_derive_impl(&input)),
+            "Expected a struct with named fields"
+        );
+    }
+
+    #[test]
+    fn test_schema_field_errors() {
+        let input: DeriveInput = parse_quote! {
+            #[variant(variant = "Answer", scenario = "You know the answer", description = "Answer")]
+            struct AnswerResponseOption {
+                capital: String,
+            }
+        };
+        assert!(error_message(response_variant_derive_impl(&input)).starts_with("Expected a #[schema("));
+
+        let input: DeriveInput = parse_quote! {
+            struct Country {
+                #[schema(description = "Capital city", example = "London")]
+                #[schema(description = "Capital city", example = "Paris")]
+                capital: String,
+            }
+        };
+        assert_eq!(
+            error_message(response_schema_derive_impl(&input)),
+            "Duplicate #[schema(...)] attribute"
+        );
+
+        let input: DeriveInput = parse_quote! {
+            enum Sentiment {
+                Positive,
+                Other(String),
+            }
+        };
+        assert_eq!(
+            error_message(response_schema_derive_impl(&input)),
+            "#[derive(ResponseSchema)] only supports enums with unit variants"
+        );
     }
 }
@@ -15,6 +15,13 @@ pub fn derive_orch_response_variant_variant(input: proc_macro::TokenStream) -> p
     derive_impl::response_variant_derive(input)
 }
 
+/// Used to derive the `OrchResponseSchema` trait for a struct or a unit enum,
+/// so that it can be used as the type of a field in a variant.
+#[proc_macro_derive(ResponseSchema, attributes(schema))]
+pub fn derive_orch_response_schema(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    derive_impl::response_schema_derive(input)
+}
+
 /// Used to construct the identifier of the derived enum.
 #[proc_macro]
 pub fn variants(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
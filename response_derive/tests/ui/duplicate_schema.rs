@@ -0,0 +1,11 @@
+use orch_response_derive::Variant;
+
+#[derive(Variant)]
+#[variant(variant = "Answer", scenario = "You know the answer", description = "Capital city")]
+pub struct AnswerVariant {
+    #[schema(description = "Capital city", example = "London")]
+    #[schema(description = "Capital city", example = "Paris")]
+    pub capital: String,
+}
+
+fn main() {}
@@ -0,0 +1,11 @@
+use orch_response_derive::Variant;
+
+#[derive(Variant)]
+#[variant(variant = "Answer", scenario = "You know the answer", description = "Capital city")]
+#[variant(variant = "Fail", scenario = "You don't know the answer", description = "Reason")]
+pub struct AnswerVariant {
+    #[schema(description = "Capital city", example = "London")]
+    pub capital: String,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use orch_response_derive::Variant;
+
+#[derive(Variant)]
+pub struct AnswerVariant {
+    #[schema(description = "Capital city", example = "London")]
+    pub capital: String,
+}
+
+fn main() {}
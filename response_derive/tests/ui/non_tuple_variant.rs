@@ -0,0 +1,8 @@
+use orch_response_derive::Variants;
+
+#[derive(Variants)]
+pub enum CapitalResponse {
+    Fail { reason: String },
+}
+
+fn main() {}
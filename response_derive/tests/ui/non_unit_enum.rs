@@ -0,0 +1,9 @@
+use orch_response_derive::ResponseSchema;
+
+#[derive(ResponseSchema)]
+pub enum Sentiment {
+    Positive,
+    Other(String),
+}
+
+fn main() {}
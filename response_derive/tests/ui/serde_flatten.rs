@@ -0,0 +1,15 @@
+use std::collections::HashMap;
+
+use orch_response_derive::Variant;
+
+#[derive(Variant, serde::Deserialize)]
+#[variant(variant = "Answer", scenario = "You know the answer", description = "Capital city")]
+pub struct AnswerVariant {
+    #[schema(description = "Capital city", example = "London")]
+    pub capital: String,
+    #[schema(description = "Other fields", example = "{}")]
+    #[serde(flatten)]
+    pub other: HashMap<String, String>,
+}
+
+fn main() {}
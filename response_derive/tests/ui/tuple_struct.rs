@@ -0,0 +1,7 @@
+use orch_response_derive::Variant;
+
+#[derive(Variant)]
+#[variant(variant = "Answer", scenario = "You know the answer", description = "Capital city")]
+pub struct AnswerVariant(String);
+
+fn main() {}
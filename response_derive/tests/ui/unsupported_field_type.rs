@@ -0,0 +1,15 @@
+use orch_response_derive::Variant;
+
+pub struct Coordinates {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+#[derive(Variant)]
+#[variant(variant = "Answer", scenario = "You know the answer", description = "Capital city")]
+pub struct AnswerVariant {
+    #[schema(description = "Coordinates of the capital city", example = "{}")]
+    pub coordinates: Coordinates,
+}
+
+fn main() {}
@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+
+use orch_response::{OrchResponseSchema, OrchResponseVariant, OrchResponseVariants};
+use orch_response_derive::{variants, ResponseSchema, Variant, Variants};
+
+#[derive(ResponseSchema, serde::Deserialize, Debug, PartialEq)]
+pub enum Sentiment {
+    Positive,
+    Negative,
+}
+
+#[derive(ResponseSchema, serde::Deserialize, Debug)]
+pub struct Reviewer {
+    #[schema(description = "Name of the reviewer", example = "Alice")]
+    pub name: String,
+    #[schema(description = "Age of the reviewer", example = "42")]
+    pub age: Option<u32>,
+}
+
+#[derive(Variants, serde::Deserialize, Debug)]
+pub enum ReviewResponse {
+    Review(ReviewResponseVariant),
+}
+
+#[derive(Variant, serde::Deserialize, Debug)]
+#[variant(variant = "Review", scenario = "You can review the text", description = "Review of the text")]
+pub struct ReviewResponseVariant {
+    #[schema(description = "Score of the review", example = "0.8")]
+    pub score: f64,
+    #[schema(description = "Sentiment of the review", example = "Positive")]
+    pub sentiment: Sentiment,
+    #[schema(description = "Reviewers", example = "[]")]
+    pub reviewers: Vec<Reviewer>,
+    #[schema(description = "Tags and their weights", example = "{}")]
+    pub tags: HashMap<String, i64>,
+}
+
+#[test]
+fn test_field_types() {
+    let variant = ReviewResponseVariant::variant();
+    let types = variant.schema.iter().map(|field| field.typ.as_str()).collect::<Vec<_>>();
+    assert_eq!(
+        types,
+        vec![
+            "number",
+            "enum(Positive|Negative)",
+            "{name: string, age?: integer?}[]",
+            "map<string, integer>"
+        ]
+    );
+
+    assert_eq!(
+        Sentiment::json_schema(),
+        serde_json::json!({ "type": "string", "enum": ["Positive", "Negative"] })
+    );
+    assert_eq!(variant.schema[0].json_schema()["examples"], serde_json::json!([0.8]));
+
+    // `Option` fields may be omitted, as serde deserializes them as `None`.
+    assert_eq!(Reviewer::json_schema()["required"], serde_json::json!(["name"]));
+    let reviewer = serde_json::from_str::<Reviewer>(r#"{"name": "Alice"}"#).unwrap();
+    assert_eq!(reviewer.age, None);
+}
+
+#[test]
+fn test_parse_and_validate() {
+    let response = serde_json::json!({
+        "response_type": "Review",
+        "score": 0.5,
+        "sentiment": "Negative",
+        "reviewers": [{ "name": "Bob", "age": null }],
+        "tags": { "rust": 2 }
+    });
+    let variants = variants!(ReviewResponse);
+    assert!(variants.validate(&response).is_ok());
+    let ReviewResponse::Review(review) = variants.parse(&response.to_string()).unwrap();
+    assert_eq!(review.sentiment, Sentiment::Negative);
+    assert_eq!(review.reviewers[0].name, "Bob");
+
+    let invalid = serde_json::json!({
+        "response_type": "Review",
+        "score": "high",
+        "sentiment": "Neutral",
+        "reviewers": [],
+        "tags": {}
+    });
+    assert!(variants.validate(&invalid).is_err());
+}
+
+#[derive(ResponseSchema, serde::Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Priority {
+    VeryHigh,
+    #[serde(rename = "meh")]
+    Low,
+}
+
+#[derive(Variants, serde::Deserialize, Debug)]
+pub enum TaskResponse {
+    Task(TaskResponseVariant),
+}
+
+#[derive(Variant, serde::Deserialize, Debug)]
+#[variant(variant = "Task", scenario = "You can create a task", description = "A task")]
+#[serde(rename_all = "camelCase")]
+pub struct TaskResponseVariant {
+    #[schema(description = "Title of the task", example = "Buy milk")]
+    pub task_title: String,
+    #[schema(description = "Identifier of the task", example = "42")]
+    #[serde(rename = "id")]
+    pub task_id: u64,
+    #[schema(description = "Priority of the task", example = "VERY_HIGH")]
+    #[serde(default = "default_priority")]
+    pub priority: Priority,
+    #[serde(skip)]
+    pub internal: bool,
+}
+
+fn default_priority() -> Priority {
+    Priority::Low
+}
+
+#[test]
+fn test_serde_attributes() {
+    let variant = TaskResponseVariant::variant();
+    let names = variant.schema.iter().map(|field| field.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, vec!["taskTitle", "id", "priority"]);
+    assert_eq!(
+        Priority::json_schema(),
+        serde_json::json!({ "type": "string", "enum": ["VERY_HIGH", "meh"] })
+    );
+    assert_eq!(
+        variant.json_schema()["required"],
+        serde_json::json!(["taskTitle", "id", "response_type"])
+    );
+
+    let response = serde_json::json!({ "response_type": "Task", "taskTitle": "Buy milk", "id": 7 });
+    let variants = variants!(TaskResponse);
+    assert!(variants.validate(&response).is_ok());
+    let TaskResponse::Task(task) = variants.parse(&response.to_string()).unwrap();
+    assert_eq!(task.task_id, 7);
+    assert_eq!(task.priority, Priority::Low);
+}